@@ -0,0 +1,374 @@
+//! An HTTP server answering JSON requests about positions, for programs
+//! that want to use the engine without linking to it.
+//!
+//! Every endpoint takes a `POST` with a body like
+//! `{"moves": "4453", "depth": 8, "movetime": 500}`, where the depth and the
+//! time in milliseconds are optional and capped by [`Limits`]. A query string
+//! after the path is ignored.
+//!
+//! - `/analyze` scores every column and gives the best move
+//! - `/solve` searches to the end of the game, within the time limit, so
+//!   it takes no depth
+//! - `/move` plays the engine's move and returns the new position
+
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::board::{self, Board, Piece};
+use crate::engine::{self, ColumnScore, Driver, Engine, SearchInfo};
+
+/// The most a single request may ask of the engine
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_depth: u32,
+    pub max_time: Duration,
+    /// Largest request body in bytes
+    pub max_body: usize,
+    /// Requests answered at once. The rest wait for a free worker.
+    pub workers: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 12,
+            max_time: Duration::from_secs(5),
+            max_body: 4096,
+            workers: 4,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    moves: String,
+    depth: Option<u32>,
+    /// Milliseconds
+    movetime: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Analysis {
+    to_move: Piece,
+    best_move: usize,
+    eval: isize,
+    depth: u32,
+    nodes: u64,
+    /// How deep the columns were scored in the time left after the search
+    columns_depth: u32,
+    columns: Vec<Column>,
+}
+
+#[derive(Debug, Serialize)]
+struct Column {
+    column: usize,
+    eval: isize,
+    exact: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Solution {
+    /// `win`, `loss` or `draw` for the side to move, or `unknown` when the
+    /// search ran out of time
+    result: &'static str,
+    /// Plies until the end of the game with best play, when known
+    plies: Option<u32>,
+    best_move: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Played {
+    #[serde(rename = "move")]
+    col: usize,
+    moves: String,
+    eval: isize,
+    /// Whether the move ended the game
+    game_over: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Error {
+    error: String,
+}
+
+/// A response body and its HTTP status
+pub type Reply = (u16, String);
+
+fn error(status: u16, message: impl Into<String>) -> Reply {
+    let body = Error { error: message.into() };
+    (status, serde_json::to_string(&body).expect("errors serialize"))
+}
+
+fn ok(body: &impl Serialize) -> Reply {
+    (200, serde_json::to_string(body).expect("responses serialize"))
+}
+
+impl Request {
+    /// The position and search limits, checked against `limits`
+    fn checked(&self, limits: &Limits) -> Result<(Vec<usize>, Board, engine::Limits), String> {
+        let moves = board::parse_moves(&self.moves)?;
+        let board = Board::from_moves(&moves)?;
+        if board.is_terminal().is_some() {
+            return Err("The game is already over".to_string());
+        }
+
+        if let Some(depth) = self.depth
+            && !(1..=limits.max_depth).contains(&depth)
+        {
+            return Err(format!("Depth must be from 1 to {}", limits.max_depth));
+        }
+        let movetime = self.movetime.map(Duration::from_millis);
+        if movetime.is_some_and(|movetime| movetime > limits.max_time) {
+            return Err(format!("Movetime must be at most {} ms", limits.max_time.as_millis()));
+        }
+
+        // Every search stops at the time limit, whatever was asked for
+        let search = engine::Limits {
+            depth: Some(self.depth.unwrap_or(limits.max_depth)),
+            movetime: Some(movetime.unwrap_or(limits.max_time)),
+        };
+
+        Ok((moves, board, search))
+    }
+}
+
+fn search(board: &Board, limits: &engine::Limits) -> SearchInfo {
+    Engine::default().search(board, limits, &AtomicBool::new(false), |_| {})
+}
+
+/// Answers one request. This is everything but the HTTP, so it can be
+/// tested on its own.
+pub fn handle(limits: &Limits, method: &str, url: &str, body: &str) -> Reply {
+    let path = url.split_once('?').map_or(url, |(path, _)| path);
+    if !matches!(path, "/analyze" | "/solve" | "/move") {
+        return error(404, format!("No endpoint {path}"));
+    }
+    if method != "POST" {
+        return error(405, "Only POST is supported");
+    }
+
+    let request: Request = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(e) => return error(400, format!("Bad request body: {e}")),
+    };
+    if path == "/solve" && request.depth.is_some() {
+        return error(400, "Solving always searches to the end of the game, so it takes no depth");
+    }
+    let (moves, board, search_limits) = match request.checked(limits) {
+        Ok(checked) => checked,
+        Err(e) => return error(422, e),
+    };
+
+    match path {
+        "/analyze" => ok(&analyze(&board, &search_limits)),
+        "/solve" => ok(&solve(&board, &search_limits)),
+        _ => ok(&play(&moves, &board, &search_limits)),
+    }
+}
+
+fn analyze(board: &Board, limits: &engine::Limits) -> Analysis {
+    let start = Instant::now();
+    let deadline = limits.movetime.map(|movetime| start + movetime);
+
+    // Half the time goes to the search, and the columns get what is left
+    let search_limits = engine::Limits {
+        movetime: limits.movetime.map(|movetime| movetime / 2),
+        ..*limits
+    };
+    let info = search(board, &search_limits);
+
+    // The columns are scored one ply deeper at a time, up to the depth the
+    // search got to, keeping the deepest set that finished in time. The
+    // first depth is always kept so there is something to show.
+    let mut columns_depth = 1;
+    let mut scores = Engine { depth: 1, ..Engine::default() }.analyze_all(board);
+    for depth in 2..=info.depth {
+        match (Engine { depth, ..Engine::default() }).analyze_until(board, deadline) {
+            Some(deeper) => (columns_depth, scores) = (depth, deeper),
+            None => break,
+        }
+    }
+
+    let columns = scores
+        .into_iter()
+        .map(|ColumnScore { col, eval, exact }| Column { column: col + 1, eval, exact })
+        .collect();
+
+    Analysis {
+        to_move: board.to_move(),
+        best_move: info.best_move + 1,
+        eval: info.eval,
+        depth: info.depth,
+        nodes: info.nodes,
+        columns_depth,
+        columns,
+    }
+}
+
+fn solve(board: &Board, limits: &engine::Limits) -> Solution {
+    let limits = engine::Limits {
+        depth: Some(board.empty_cells() as u32),
+        movetime: limits.movetime,
+    };
+    // MTD(f) gets deeper than plain alpha-beta in the same time
+    let engine = Engine { driver: Driver::Mtdf, ..Engine::default() };
+    let info = engine.search(board, &limits, &AtomicBool::new(false), |_| {});
+
+    let (result, plies) = match info.plies_to_end() {
+        Some(plies) if info.eval > 0 => ("win", Some(plies)),
+        Some(plies) => ("loss", Some(plies)),
+        None if info.depth as usize >= board.empty_cells() => ("draw", Some(board.empty_cells() as u32)),
+        None => ("unknown", None),
+    };
+
+    Solution { result, plies, best_move: info.best_move + 1 }
+}
+
+fn play(moves: &[usize], board: &Board, limits: &engine::Limits) -> Played {
+    let info = search(board, limits);
+    let mut board = board.clone();
+    board.insert_piece(info.best_move, board.to_move()).expect("the engine plays legal moves");
+
+    Played {
+        col: info.best_move + 1,
+        moves: board::format_moves(&[moves, &[info.best_move]].concat()),
+        eval: info.eval,
+        game_over: board.is_terminal().is_some(),
+    }
+}
+
+/// Serves requests on `address` until the process ends, answering up to
+/// `limits.workers` of them at once
+pub fn serve(address: &str, limits: Limits) -> io::Result<()> {
+    let server = Server::http(address).map_err(io::Error::other)?;
+    run(Arc::new(server), limits);
+    Ok(())
+}
+
+fn run(server: Arc<Server>, limits: Limits) {
+    let workers: Vec<_> = (0..limits.workers.max(1))
+        .map(|_| {
+            let server = server.clone();
+            thread::spawn(move || work(&server, &limits))
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().ok();
+    }
+}
+
+/// Answers requests one at a time until the server is unblocked
+fn work(server: &Server, limits: &Limits) {
+    while let Ok(mut request) = server.recv() {
+        let mut body = String::new();
+        let read = request
+            .as_reader()
+            .take(limits.max_body as u64 + 1)
+            .read_to_string(&mut body);
+
+        let (status, json) = match read {
+            Err(_) => error(400, "Request body must be UTF-8"),
+            Ok(n) if n > limits.max_body => error(413, "Request body is too large"),
+            Ok(_) => {
+                let method = if *request.method() == Method::Post { "POST" } else { "other" };
+                handle(limits, method, request.url(), &body)
+            }
+        };
+
+        let content_type = Header::from_bytes("Content-Type", "application/json").expect("header is valid");
+        let response = Response::from_string(json).with_status_code(status).with_header(content_type);
+        request.respond(response).ok();
+    }
+}
+
+#[cfg(test)]
+mod api_tests {
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    use super::*;
+
+    fn limits() -> Limits {
+        Limits { max_depth: 4, ..Limits::default() }
+    }
+
+    #[test]
+    fn analyze_finds_the_win() {
+        let (status, body) = handle(&limits(), "POST", "/analyze", r#"{"moves": "112233"}"#);
+
+        assert_eq!(status, 200);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["best_move"], 4);
+        assert_eq!(json["to_move"], "O");
+        assert_eq!(json["columns"][0]["column"], 4);
+    }
+
+    #[test]
+    fn solve_and_move() {
+        let (_, body) = handle(&limits(), "POST", "/solve", r#"{"moves": "112233"}"#);
+        assert!(body.contains(r#""result":"win","plies":1"#));
+
+        let (_, body) = handle(&limits(), "POST", "/move", r#"{"moves": " 112233 "}"#);
+        assert!(body.contains(r#""moves":"1122334""#));
+        assert!(body.contains(r#""game_over":true"#));
+
+        let (status, body) = handle(&limits(), "POST", "/solve", r#"{"moves": "4", "depth": 3}"#);
+        assert_eq!(status, 400);
+        assert!(body.contains("takes no depth"));
+    }
+
+    #[test]
+    fn requests_over_the_limits_are_refused() {
+        let (status, body) = handle(&limits(), "POST", "/analyze", r#"{"moves": "4", "depth": 20}"#);
+        assert_eq!(status, 422);
+        assert!(body.contains("Depth must be from 1 to 4"));
+
+        assert_eq!(handle(&limits(), "POST", "/analyze", r#"{"moves": "4", "movetime": 60000}"#).0, 422);
+        assert_eq!(handle(&limits(), "POST", "/analyze", "not json").0, 400);
+        assert_eq!(handle(&limits(), "GET", "/analyze", "").0, 405);
+        assert_eq!(handle(&limits(), "POST", "/nothing", "").0, 404);
+    }
+
+    #[test]
+    fn columns_are_scored_within_the_time_limit() {
+        let start = Instant::now();
+        let (status, body) = handle(&Limits::default(), "POST", "/analyze?pretty=1", r#"{"moves": "", "movetime": 100}"#);
+
+        assert_eq!(status, 200);
+        assert!(start.elapsed() < Duration::from_secs(1), "took {:?}", start.elapsed());
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["columns"].as_array().unwrap().len(), 7);
+        assert!(json["columns_depth"].as_u64().unwrap() <= json["depth"].as_u64().unwrap());
+    }
+
+    #[test]
+    fn serves_over_http() {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let address = server.server_addr().to_ip().unwrap();
+        let listening = server.clone();
+        thread::spawn(move || run(listening, limits()));
+
+        let body = r#"{"moves": "112233"}"#;
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "POST /move HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#""move":4"#));
+        server.unblock();
+    }
+}
@@ -0,0 +1,274 @@
+//! Matches between two engine configurations, for telling whether a change
+//! to the engine made it stronger.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::board::{self, Piece};
+use crate::record::{GameRecord, GameResult, PlayerRecord};
+use crate::{Game, Player};
+
+/// How a match is played
+#[derive(Clone, Debug)]
+pub struct MatchOptions {
+    pub games: usize,
+    /// Moves to start games from. Each opening is played twice, once with
+    /// each player moving first.
+    pub openings: Vec<Vec<usize>>,
+    /// Time engine programs get per move
+    pub movetime: Duration,
+    /// Where to save every game as JSON
+    pub save_to: Option<PathBuf>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            games: 10,
+            openings: Vec::new(),
+            movetime: Duration::from_secs(1),
+            save_to: None,
+        }
+    }
+}
+
+/// The games of a match, with the score counted for the first player
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MatchResult {
+    pub a: Option<PlayerRecord>,
+    pub b: Option<PlayerRecord>,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub games: Vec<GameRecord>,
+}
+
+/// Reads an opening suite with one move list like `4453` per line. Blank
+/// lines and lines starting with `#` are skipped.
+pub fn parse_openings(text: &str) -> Result<Vec<Vec<usize>>, &'static str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let moves = board::parse_moves(line)?;
+            board::Board::from_moves(&moves)?;
+            Ok(moves)
+        })
+        .collect()
+}
+
+/// Whether `player` always plays the same move in the same position. The
+/// Monte Carlo search has a fixed seed, and engine programs may not be.
+fn deterministic(player: &PlayerRecord) -> bool {
+    matches!(player, PlayerRecord::Engine { .. } | PlayerRecord::Tuned { .. } | PlayerRecord::Mcts { .. })
+}
+
+/// Plays `options.games` games between `a` and `b`, calling `progress` after
+/// each one. `a` moves first in the even numbered games.
+///
+/// Two deterministic players replay the same game whenever an opening comes
+/// round again with the same colours, and repeated games would make the
+/// confidence interval look narrower than it is. Such matches need an
+/// opening for every two games.
+pub fn run_match(
+    a: &PlayerRecord,
+    b: &PlayerRecord,
+    options: &MatchOptions,
+    mut progress: impl FnMut(usize, &GameRecord),
+) -> io::Result<MatchResult> {
+    let mut result = MatchResult {
+        a: Some(a.clone()),
+        b: Some(b.clone()),
+        ..MatchResult::default()
+    };
+
+    let needed = options.games.div_ceil(2);
+    if deterministic(a) && deterministic(b) && options.openings.len() < needed {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Both players repeat their games, so {} games need {needed} different openings", options.games),
+        ));
+    }
+
+    if let Some(dir) = &options.save_to {
+        fs::create_dir_all(dir)?;
+    }
+
+    for i in 0..options.games {
+        let a_first = i.is_multiple_of(2);
+        let (o, x) = if a_first { (a, b) } else { (b, a) };
+        let opening = match options.openings.len() {
+            0 => &[][..],
+            n => &options.openings[(i / 2) % n][..],
+        };
+
+        let record = play_game(o, x, opening, options.movetime)?;
+        if let Some(dir) = &options.save_to {
+            fs::write(dir.join(format!("game-{}.json", i + 1)), record.to_json()? + "\n")?;
+        }
+
+        let a_piece = if a_first { Piece::O } else { Piece::X };
+        match winner(record.result) {
+            None => result.draws += 1,
+            Some(piece) if piece == a_piece => result.wins += 1,
+            Some(_) => result.losses += 1,
+        }
+
+        progress(i, &record);
+        result.games.push(record);
+    }
+
+    Ok(result)
+}
+
+/// Plays one game from `opening` with `o` moving first
+pub fn play_game(o: &PlayerRecord, x: &PlayerRecord, opening: &[usize], movetime: Duration) -> io::Result<GameRecord> {
+    let mut game = Game::with_players(Player::launch(x, movetime)?, Player::launch(o, movetime)?);
+    for &col in opening {
+        game.play(col).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    }
+
+    game.play_out();
+    Ok(game.record())
+}
+
+/// The piece that won, or `None` for a draw or an unfinished game
+pub fn winner(result: GameResult) -> Option<Piece> {
+    match result {
+        GameResult::Win(piece) => Some(piece),
+        GameResult::Resigned(piece) | GameResult::TimeForfeit(piece) => Some(piece.opponent()),
+        GameResult::Draw | GameResult::Ongoing => None,
+    }
+}
+
+/// The rating difference that makes `score`, the expected share of points,
+/// likely
+pub fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl MatchResult {
+    pub fn played(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The share of points the first player scored, from 0 to 1
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.played().max(1) as f64
+    }
+
+    /// The 95% confidence interval of the score, using the normal
+    /// approximation of the per game results
+    pub fn score_interval(&self) -> (f64, f64) {
+        let n = self.played().max(1) as f64;
+        let score = self.score();
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / n;
+        let margin = 1.96 * (variance / n).sqrt();
+
+        ((score - margin).max(0.0), (score + margin).min(1.0))
+    }
+
+    /// The Elo difference of the first player over the second, infinite when
+    /// one of them scored every point
+    pub fn elo(&self) -> f64 {
+        elo_from_score(self.score())
+    }
+
+    /// The Elo difference at both ends of [`MatchResult::score_interval`]
+    pub fn elo_interval(&self) -> (f64, f64) {
+        let (low, high) = self.score_interval();
+        (elo_from_score(low), elo_from_score(high))
+    }
+}
+
+fn format_elo(elo: f64) -> String {
+    if elo.is_infinite() {
+        if elo > 0.0 { "+inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{elo:+.0}")
+    }
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |player: &Option<PlayerRecord>| player.as_ref().map(|p| p.to_string()).unwrap_or_default();
+        let (low, high) = self.score_interval();
+        let (elo_low, elo_high) = self.elo_interval();
+
+        writeln!(f, "{} vs {}, {} games", name(&self.a), name(&self.b), self.played())?;
+        writeln!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)?;
+        writeln!(
+            f,
+            "Score {:.1}% (95% interval {:.1}% to {:.1}%)",
+            self.score() * 100.0,
+            low * 100.0,
+            high * 100.0
+        )?;
+        write!(
+            f,
+            "Elo difference {} (95% interval {} to {})",
+            format_elo(self.elo()),
+            format_elo(elo_low),
+            format_elo(elo_high)
+        )
+    }
+}
+
+#[cfg(test)]
+mod arena_tests {
+    use super::*;
+
+    #[test]
+    fn score_and_elo() {
+        let result = MatchResult { wins: 6, draws: 2, losses: 2, ..MatchResult::default() };
+
+        assert_eq!(result.score(), 0.7);
+        assert_eq!(elo_from_score(0.5), 0.0);
+        assert!((result.elo() - 147.2).abs() < 0.1);
+
+        let (low, high) = result.score_interval();
+        assert!(low < 0.7 && high > 0.7);
+        assert!(result.to_string().contains("+6 =2 -2"));
+    }
+
+    #[test]
+    fn openings_skip_comments_and_reject_bad_lines() {
+        assert_eq!(parse_openings("# suite\n44\n\n43\n"), Ok(vec![vec![3, 3], vec![3, 2]]));
+        assert!(parse_openings("48").is_err());
+    }
+
+    #[test]
+    fn match_alternates_the_first_player() {
+        let options = MatchOptions {
+            games: 2,
+            openings: vec![vec![3]],
+            ..MatchOptions::default()
+        };
+        let (a, b) = (PlayerRecord::Engine { depth: 1 }, PlayerRecord::Engine { depth: 2 });
+
+        let result = run_match(&a, &b, &options, |_, _| {}).unwrap();
+
+        assert_eq!(result.played(), 2);
+        assert_eq!(result.games[0].o, a);
+        assert_eq!(result.games[1].o, b);
+        assert!(result.games.iter().all(|game| game.moves[0] == 3 && game.result != GameResult::Ongoing));
+    }
+
+    #[test]
+    fn deterministic_players_need_an_opening_for_every_two_games() {
+        let (a, b) = (PlayerRecord::Engine { depth: 1 }, PlayerRecord::Mcts { playouts: 10 });
+        let options = MatchOptions { games: 3, openings: vec![vec![3]], ..MatchOptions::default() };
+
+        let error = run_match(&a, &b, &options, |_, _| {}).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("3 games need 2 different openings"));
+        assert!(run_match(&a, &b, &MatchOptions { games: 2, ..MatchOptions::default() }, |_, _| {}).is_err());
+    }
+}
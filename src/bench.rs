@@ -0,0 +1,342 @@
+//! Benchmarks on fixed sets of positions, for catching changes that make
+//! the engine slower or wrong.
+//!
+//! Position files have one position per line, a move list followed by its
+//! score, like `4453 -2`, in the format of the test sets published for
+//! Connect Four solvers. The score is 0 for a draw, and otherwise positive
+//! when the side to move wins: 22 less the number of pieces the winner has
+//! played by the end, so quicker wins score higher. A loss is the negative
+//! of the opponent's score.
+//!
+//! The built in sets are random positions, named for how many pieces have
+//! been played and how long the game lasts with best play: easy ones are
+//! over within 15 plies, hard ones last longer. This is not the same as the
+//! published sets, which cannot be shipped here, so their files have to be
+//! passed in. Every expected score was checked with a reference solver that
+//! shares no code with the engine, in the tests of this module.
+
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::board::{self, Board};
+use crate::engine::{Driver, Engine, Limits, SearchInfo};
+
+/// The built in sets. Openings that last long take the engine too long to
+/// solve, so there is no hard opening set, and the hard middle games were
+/// picked from those it solves in under ten seconds.
+pub const SUITES: [(&str, &str); 4] = [
+    ("end-easy", include_str!("../bench/end-easy.txt")),
+    ("middle-easy", include_str!("../bench/middle-easy.txt")),
+    ("middle-hard", include_str!("../bench/middle-hard.txt")),
+    ("opening-easy", include_str!("../bench/opening-easy.txt")),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    pub moves: Vec<usize>,
+    pub score: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Search every position to the end and check the score
+    Solve,
+    /// Pick a move like in a game, checking only the forced results the
+    /// engine finds
+    Engine,
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchOptions {
+    pub mode: Mode,
+    pub engine: Engine,
+    /// Time allowed per position, after which it counts as unsolved
+    pub movetime: Option<Duration>,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions {
+            mode: Mode::Solve,
+            engine: Engine { driver: Driver::Mtdf, ..Engine::default() },
+            movetime: None,
+        }
+    }
+}
+
+/// The totals for one set of positions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub name: String,
+    pub positions: usize,
+    pub correct: usize,
+    pub wrong: usize,
+    /// Positions the search did not get to a result in
+    pub unsolved: usize,
+    pub time: Duration,
+    pub nodes: u64,
+}
+
+/// Reads a position file. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn parse_positions(text: &str) -> Result<Vec<Position>, &'static str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (moves, score) = line.split_once(' ').ok_or("Expected a move list and a score")?;
+            let moves = board::parse_moves(moves)?;
+            let board = Board::from_moves(&moves)?;
+            if board.is_terminal().is_some() {
+                return Err("The game is already over");
+            }
+            let score = score.trim().parse().map_err(|_| "Scores must be whole numbers")?;
+            Ok(Position { moves, score })
+        })
+        .collect()
+}
+
+/// The score of a search result in the format of the position files, or
+/// `None` if the search did not reach the end of the game
+pub fn score(board: &Board, info: &SearchInfo) -> Option<i32> {
+    let played = board.move_count() as i32;
+
+    match info.plies_to_end() {
+        // The winning piece is number `played + plies` on the board
+        Some(plies) => {
+            let score = (44 - played - plies as i32) / 2;
+            Some(if info.eval > 0 { score } else { -score })
+        }
+        None if info.depth as usize >= board.empty_cells() => Some(0),
+        None => None,
+    }
+}
+
+/// Runs every position, calling `progress` with each one and the score
+/// found for it
+pub fn run(name: &str, positions: &[Position], options: &BenchOptions, mut progress: impl FnMut(&Position, Option<i32>)) -> Report {
+    let mut report = Report {
+        name: name.to_string(),
+        positions: positions.len(),
+        ..Report::default()
+    };
+
+    for position in positions {
+        let board = Board::from_moves(&position.moves).expect("positions were checked when parsed");
+        let limits = match options.mode {
+            Mode::Solve => Limits { depth: Some(board.empty_cells() as u32), movetime: options.movetime },
+            Mode::Engine => Limits { depth: Some(options.engine.depth), movetime: options.movetime },
+        };
+
+        let start = Instant::now();
+        let info = options.engine.search(&board, &limits, &AtomicBool::new(false), |_| {});
+        report.time += start.elapsed();
+        report.nodes += info.nodes;
+
+        let found = score(&board, &info);
+        match found {
+            Some(score) if score == position.score => report.correct += 1,
+            Some(_) => report.wrong += 1,
+            None => report.unsolved += 1,
+        }
+        progress(position, found);
+    }
+
+    report
+}
+
+impl Report {
+    pub fn mean_time(&self) -> Duration {
+        self.time / self.positions.max(1) as u32
+    }
+
+    pub fn mean_nodes(&self) -> u64 {
+        self.nodes / self.positions.max(1) as u64
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} positions, mean {:.1} ms and {} nodes, {} correct, {} wrong, {} unsolved",
+            self.name,
+            self.positions,
+            self.mean_time().as_secs_f64() * 1000.0,
+            self.mean_nodes(),
+            self.correct,
+            self.wrong,
+            self.unsolved
+        )
+    }
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use super::*;
+
+    #[test]
+    fn scores_count_the_winners_pieces() {
+        assert_eq!(parse_positions("112233 x"), Err("Scores must be whole numbers"));
+        assert_eq!(parse_positions("1122334 18"), Err("The game is already over"));
+
+        // O wins with its fourth piece, the seventh on the board
+        let positions = parse_positions("# a win and a loss\n112233 18\n22334 -18\n").unwrap();
+        let report = run("wins", &positions, &BenchOptions::default(), |_, _| {});
+
+        assert_eq!((report.correct, report.wrong, report.unsolved), (2, 0, 0));
+    }
+
+    #[test]
+    fn built_in_end_games_are_solved() {
+        let positions = parse_positions(SUITES[0].1).unwrap();
+        let positions = &positions[..5];
+
+        let report = run(SUITES[0].0, positions, &BenchOptions::default(), |_, _| {});
+
+        assert_eq!(report.correct, 5, "{report}");
+        assert!(report.to_string().starts_with("end-easy: 5 positions"));
+    }
+
+    /// A solver written apart from the engine, on bitboards, to check the
+    /// expected scores of the built in sets. Each column takes seven bits,
+    /// the bottom six for its cells.
+    mod reference {
+        use std::collections::HashMap;
+
+        const ORDER: [usize; 7] = [3, 2, 4, 1, 5, 0, 6];
+
+        #[derive(Clone, Copy)]
+        pub struct Position {
+            /// The pieces of the side to move
+            current: u64,
+            /// Every piece
+            mask: u64,
+            played: i32,
+        }
+
+        fn bottom(col: usize) -> u64 {
+            1 << (col * 7)
+        }
+
+        fn four_in_a_row(pieces: u64) -> bool {
+            // Across, both diagonals and up
+            [7, 6, 8, 1].into_iter().any(|shift| {
+                let pairs = pieces & (pieces >> shift);
+                pairs & (pairs >> (2 * shift)) != 0
+            })
+        }
+
+        impl Position {
+            pub fn new(moves: &[usize]) -> Position {
+                let mut position = Position { current: 0, mask: 0, played: 0 };
+                for &col in moves {
+                    position.play(col);
+                }
+                position
+            }
+
+            fn can_play(&self, col: usize) -> bool {
+                self.mask & (1 << (5 + col * 7)) == 0
+            }
+
+            fn play(&mut self, col: usize) {
+                self.current ^= self.mask;
+                self.mask |= self.mask + bottom(col);
+                self.played += 1;
+            }
+
+            fn wins_with(&self, col: usize) -> bool {
+                let cell = (self.mask + bottom(col)) & (0x3f << (col * 7));
+                four_in_a_row(self.current | cell)
+            }
+        }
+
+        /// Upper bounds on scores by position
+        #[derive(Default)]
+        pub struct Solver {
+            known: HashMap<u64, i32>,
+        }
+
+        impl Solver {
+            /// The score of `position` in the format of the position files
+            pub fn solve(&mut self, position: Position) -> i32 {
+                let (mut lower, mut upper) = (-(42 - position.played) / 2, (43 - position.played) / 2);
+
+                // Null-window searches, tried towards zero first since
+                // most positions are closer to a draw than to the extremes
+                while lower < upper {
+                    let mut middle = lower + (upper - lower) / 2;
+                    if middle <= 0 && lower / 2 < middle {
+                        middle = lower / 2;
+                    } else if middle >= 0 && upper / 2 > middle {
+                        middle = upper / 2;
+                    }
+
+                    let score = self.negamax(position, middle, middle + 1);
+                    if score <= middle {
+                        upper = score;
+                    } else {
+                        lower = score;
+                    }
+                }
+
+                lower
+            }
+
+            fn negamax(&mut self, position: Position, mut alpha: i32, mut beta: i32) -> i32 {
+                if position.played == 42 {
+                    return 0;
+                }
+                if (0..7).any(|col| position.can_play(col) && position.wins_with(col)) {
+                    return (43 - position.played) / 2;
+                }
+
+                let key = position.current + position.mask;
+                let upper = self.known.get(&key).copied().unwrap_or((41 - position.played) / 2);
+                if beta > upper {
+                    beta = upper;
+                    if alpha >= beta {
+                        return beta;
+                    }
+                }
+
+                for col in ORDER.into_iter().filter(|&col| position.can_play(col)) {
+                    let mut next = position;
+                    next.play(col);
+                    let score = -self.negamax(next, -beta, -alpha);
+                    if score >= beta {
+                        return score;
+                    }
+                    alpha = alpha.max(score);
+                }
+
+                self.known.insert(key, alpha);
+                alpha
+            }
+        }
+    }
+
+    #[test]
+    fn reference_solver_scores_the_win_and_loss() {
+        let positions = parse_positions("112233 18\n22334 -18\n").unwrap();
+
+        for position in positions {
+            let score = reference::Solver::default().solve(reference::Position::new(&position.moves));
+            assert_eq!(score, position.score);
+        }
+    }
+
+    /// Runs with `cargo test --release`, as it takes a while in debug builds
+    #[test]
+    #[cfg_attr(debug_assertions, ignore)]
+    fn built_in_scores_match_the_reference_solver() {
+        for (name, text) in SUITES {
+            for position in parse_positions(text).unwrap() {
+                let score = reference::Solver::default().solve(reference::Position::new(&position.moves));
+                assert_eq!(score, position.score, "{name}: {}", board::format_moves(&position.moves));
+            }
+        }
+    }
+}
@@ -1,3 +1,9 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::render::{Ascii, Renderer};
+
 #[derive(Clone, Debug)]
 pub struct Board {
     cols: [[Piece; 6]; 7],
@@ -5,7 +11,7 @@ pub struct Board {
     pub eval: isize,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Piece {
     X,
     O,
@@ -37,6 +43,23 @@ impl Board {
         }
     }
 
+    /// Plays the given columns in order, starting with O
+    pub fn from_moves(moves: &[usize]) -> Result<Board, &'static str> {
+        let mut board = Board::new();
+
+        for &col in moves {
+            if board.is_terminal().is_some() {
+                return Err("Game is already over");
+            }
+            if col >= board.cols.len() {
+                return Err("Column does not exist");
+            }
+            board.insert_piece(col, board.to_move())?;
+        }
+
+        Ok(board)
+    }
+
     pub fn insert_piece(&mut self, col: usize, piece: Piece) -> Result<(), &'static str> {
         if !self.cols[col].contains(&Piece::Empty) {
             return Err("Column is full");
@@ -57,6 +80,21 @@ impl Board {
         Ok(())
     }
 
+    /// A number that is different for every position, for tables of
+    /// positions. Each column takes seven bits: a one above its pieces,
+    /// with the O pieces below it set.
+    pub fn key(&self) -> u64 {
+        self.cols.iter().enumerate().fold(0, |key, (i, col)| {
+            let mut bits = 1 << self.column_height(i);
+            for (row, &piece) in col.iter().enumerate() {
+                if piece == Piece::O {
+                    bits |= 1 << row;
+                }
+            }
+            key | bits << (7 * i)
+        })
+    }
+
     pub fn undo_move(&mut self, col: usize) {
         for row in (0..self.cols[col].len()).rev() {
             if self.cols[col][row] != Piece::Empty {
@@ -126,6 +164,84 @@ impl Board {
     }
 
 
+    /// The cells `(col, row)` of a four in a row anywhere on the board.
+    ///
+    /// Unlike [`Board::check_win`] this does not rely on the last move, so it
+    /// also works for boards built up by hand.
+    pub fn winning_line(&self) -> Option<[(usize, usize); 4]> {
+        let cols = self.cols.len() as isize;
+        let rows = self.cols[0].len() as isize;
+
+        for x in 0..cols {
+            for y in 0..rows {
+                let piece = self.cols[x as usize][y as usize];
+                if piece == Piece::Empty {
+                    continue;
+                }
+
+                // Right, up, up-right and down-right cover every line once
+                for (dx, dy) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+                    let (end_x, end_y) = (x + 3 * dx, y + 3 * dy);
+                    if end_x >= cols || end_y < 0 || end_y >= rows {
+                        continue;
+                    }
+
+                    let line = [0, 1, 2, 3].map(|i| ((x + i * dx) as usize, (y + i * dy) as usize));
+                    if line.iter().all(|&(cx, cy)| self.cols[cx][cy] == piece) {
+                        return Some(line);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Empty cells `(col, row)` where `piece` would complete four in a row,
+    /// whether or not the cell can be played yet
+    pub fn threats(&self, piece: Piece) -> Vec<(usize, usize)> {
+        let mut threats = Vec::new();
+
+        for x in 0..self.cols.len() {
+            for y in 0..self.cols[0].len() {
+                if self.cols[x][y] == Piece::Empty && self.completes_four(x, y, piece) {
+                    threats.push((x, y));
+                }
+            }
+        }
+
+        threats
+    }
+
+    /// Whether a `piece` at `(col, row)` would be part of four in a row
+    fn completes_four(&self, col: usize, row: usize, piece: Piece) -> bool {
+        let cols = self.cols.len() as isize;
+        let rows = self.cols[0].len() as isize;
+
+        let count = |dx: isize, dy: isize| {
+            let (mut x, mut y) = (col as isize + dx, row as isize + dy);
+            let mut count = 0;
+            while x >= 0 && x < cols && y >= 0 && y < rows && self.cols[x as usize][y as usize] == piece {
+                count += 1;
+                x += dx;
+                y += dy;
+            }
+            count
+        };
+
+        [(1, 0), (0, 1), (1, 1), (1, -1)]
+            .iter()
+            .any(|&(dx, dy)| count(dx, dy) + count(-dx, -dy) >= 3)
+    }
+
+    /// The cell `(col, row)` of the piece that was played last
+    pub fn last_cell(&self) -> Option<(usize, usize)> {
+        match self.column_height(self.last_move) {
+            0 => None,
+            height => Some((self.last_move, height - 1)),
+        }
+    }
+
     pub fn creates_three_in_a_row(&mut self, col: usize,  piece: Piece) -> bool {
         self.insert_piece(col, piece).ok();
 
@@ -238,29 +354,6 @@ impl Board {
         (diag1, diag2)
     }
 
-    pub fn display(&self) {
-        clearscreen::clear().expect("failed to clear screen");
-
-        println!("Eval: {}", self.eval);
-        println!();
-
-        for x in 1..=7 {
-            print!("{x} ");
-        }
-        println!();
-        for y in (0..6).rev() {
-            for x in 0..7 {
-                match self.cols[x][y] {
-                    Piece::X => print!("\x1b[31mX\x1b[0m "), // Prints 'X' with red text
-                    Piece::O => print!("\x1b[33mO\x1b[0m "), // Prints 'O' with yellow text
-                    Piece::Empty => print!("\x1b[34m#\x1b[0m "), // prints '#' with blue text
-                }
-            }
-            println!()
-        }
-        println!();
-    }
-
     pub fn diagonals(&self) -> (Vec<Vec<Piece>>, Vec<Vec<Piece>>) {
         let cols = self.cols.len();
         let rows = self.cols[0].len();
@@ -352,15 +445,105 @@ impl Board {
 
     pub fn get_moves(&self) -> Vec<usize> {
         let mut empty_cols = Vec::new();
-        let cols = self.cols();
-        for i in 0..cols.len() {
-            if cols[i].contains(&Piece::Empty) {
+        for (i, col) in self.cols.iter().enumerate() {
+            if col.contains(&Piece::Empty) {
                 empty_cols.push(i);
             };
         }
 
         empty_cols
     }
+
+    /// Number of pieces in a column, which is also the row the next piece lands in
+    pub fn column_height(&self, col: usize) -> usize {
+        self.cols[col].iter().filter(|&&p| p != Piece::Empty).count()
+    }
+
+    pub fn get(&self, col: usize, row: usize) -> Piece {
+        self.cols[col][row]
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.cols
+            .iter()
+            .flatten()
+            .filter(|&&p| p != Piece::Empty)
+            .count()
+    }
+
+    pub fn empty_cells(&self) -> usize {
+        self.cols.len() * self.cols[0].len() - self.move_count()
+    }
+
+    /// Columns where `piece` would complete four in a row
+    pub fn winning_moves(&self, piece: Piece) -> Vec<usize> {
+        let mut board = self.clone();
+        let mut moves = Vec::new();
+
+        for col in self.get_moves() {
+            board.insert_piece(col, piece).expect("column has space");
+            if board.check_win() == Some(piece) {
+                moves.push(col);
+            }
+            board.undo_move(col);
+        }
+
+        moves
+    }
+
+    /// The piece whose turn it is, assuming O made the first move
+    pub fn to_move(&self) -> Piece {
+        if self.move_count().is_multiple_of(2) {
+            Piece::O
+        } else {
+            Piece::X
+        }
+    }
+
+    /// Counts the positions reached by every sequence of `depth` moves.
+    /// Finished games are not played on, so a win before the last move
+    /// adds nothing. Knowing the counts for the empty board catches
+    /// mistakes in making, undoing and generating moves.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        if self.is_terminal().is_some() {
+            return 0;
+        }
+
+        let piece = self.to_move();
+        let mut count = 0;
+        for col in self.get_moves() {
+            self.insert_piece(col, piece).expect("column has space");
+            count += self.perft(depth - 1);
+            self.undo_move(col);
+        }
+
+        count
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Ascii.render(self))
+    }
+}
+
+/// Parses a move list written as column numbers from 1 to 7, like `4453`
+pub fn parse_moves(s: &str) -> Result<Vec<usize>, &'static str> {
+    s.trim()
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d @ 1..=7) => Ok(d as usize - 1),
+            _ => Err("Moves must be column numbers from 1 to 7"),
+        })
+        .collect()
+}
+
+/// Writes a move list as column numbers from 1 to 7, the inverse of [`parse_moves`]
+pub fn format_moves(moves: &[usize]) -> String {
+    moves.iter().map(|col| (col + 1).to_string()).collect()
 }
 
 #[cfg(test)]
@@ -590,4 +773,66 @@ mod board_tests {
         let win = board.check_win();
         assert_eq!(win, Some(Piece::X));
     }
+
+    #[test]
+    fn moves_round_trip() {
+        let moves = parse_moves("4453").unwrap();
+
+        assert_eq!(moves, vec![3, 3, 4, 2]);
+        assert_eq!(format_moves(&moves), "4453");
+        assert!(parse_moves("4483").is_err());
+    }
+
+    #[test]
+    fn from_moves_rejects_moves_after_win() {
+        let board = Board::from_moves(&[0, 1, 0, 1, 0, 1, 0]).unwrap();
+        assert_eq!(board.check_win(), Some(Piece::O));
+
+        assert!(Board::from_moves(&[0, 1, 0, 1, 0, 1, 0, 1]).is_err());
+        assert!(Board::from_moves(&[0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn winning_line_finds_diagonal() {
+        // O builds the diagonal from the bottom left corner
+        let board = Board::from_moves(&[0, 1, 1, 2, 2, 3, 2, 3, 3, 6, 3]).unwrap();
+
+        assert_eq!(board.winning_line(), Some([(0, 0), (1, 1), (2, 2), (3, 3)]));
+        assert_eq!(board.last_cell(), Some((3, 3)));
+    }
+
+    #[test]
+    fn perft_counts_match_the_empty_board() {
+        // Every sequence is legal until a column overflows at 7 moves
+        let counts = [1, 7, 49, 343, 2401, 16807, 117649, 823536];
+        let mut board = Board::new();
+
+        for (depth, &count) in counts.iter().enumerate() {
+            assert_eq!(board.perft(depth as u32), count, "depth {depth}");
+        }
+        assert_eq!(board.key(), Board::new().key());
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore)]
+    fn perft_counts_past_the_first_wins() {
+        // From depth 8 wins cut games short. These take too long for a debug
+        // build, so they run with `cargo test --release`.
+        let counts = [(8, 5673234), (9, 39394572)];
+        let mut board = Board::new();
+
+        for (depth, count) in counts {
+            assert_eq!(board.perft(depth), count, "depth {depth}");
+        }
+        assert_eq!(board.key(), Board::new().key());
+    }
+
+    #[test]
+    fn perft_stops_at_wins() {
+        // One of O's seven moves wins, leaving six for X to answer
+        let mut board = Board::from_moves(&[0, 0, 1, 1, 2, 2]).unwrap();
+
+        assert_eq!(board.perft(2), 42);
+        assert_eq!(Board::from_moves(&[0, 0, 1, 1, 2, 2, 3]).unwrap().perft(1), 0);
+    }
 }
@@ -0,0 +1,276 @@
+//! Chess style clocks. Each side has its own time, which only runs on its
+//! turn, and loses the game when it runs out.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::board::Piece;
+
+/// How much time each side gets
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimeControl {
+    /// Time on each clock at the start
+    pub base: Duration,
+    /// Added to a clock after every move made in time
+    pub increment: Duration,
+    /// How long into each turn the clock starts running
+    pub delay: Duration,
+}
+
+/// What a player is told about their clock at the start of their turn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeLeft {
+    pub remaining: Duration,
+    pub increment: Duration,
+    pub delay: Duration,
+}
+
+/// Both sides' clocks, at most one of them running
+#[derive(Clone, Debug)]
+pub struct Clock {
+    pub control: TimeControl,
+    o: Duration,
+    x: Duration,
+    running: Option<(Piece, Instant)>,
+}
+
+/// Longest base time, increment or delay. It is far more than any game
+/// needs and keeps the deadlines worked out from the clock in range.
+const MAX_TIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+fn seconds(s: &str) -> Result<Duration, &'static str> {
+    let secs = s.parse::<f64>().map_err(|_| "Times must be positive numbers")?;
+    Duration::try_from_secs_f64(secs).map_err(|_| "Times must be positive numbers")
+}
+
+fn at_most_a_week(time: Option<Duration>) -> Result<Duration, &'static str> {
+    time.filter(|&time| time <= MAX_TIME).ok_or("Times must be at most a week")
+}
+
+impl FromStr for TimeControl {
+    type Err = &'static str;
+
+    /// Parses minutes, optionally followed by `+` and an increment or `d`
+    /// and a delay in seconds, like `5`, `3+2` or `1d5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, increment, delay) = if let Some((base, increment)) = s.split_once('+') {
+            (base, seconds(increment)?, Duration::ZERO)
+        } else if let Some((base, delay)) = s.split_once('d') {
+            (base, Duration::ZERO, seconds(delay)?)
+        } else {
+            (s, Duration::ZERO, Duration::ZERO)
+        };
+
+        let base = at_most_a_week(seconds(base)?.checked_mul(60))?;
+        let (increment, delay) = (at_most_a_week(Some(increment))?, at_most_a_week(Some(delay))?);
+        if base.is_zero() {
+            return Err("The base time must be more than zero");
+        }
+
+        Ok(TimeControl { base, increment, delay })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.base.as_secs_f64() / 60.0)?;
+        if !self.increment.is_zero() {
+            write!(f, "+{}", self.increment.as_secs_f64())?;
+        }
+        if !self.delay.is_zero() {
+            write!(f, "d{}", self.delay.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+impl TimeLeft {
+    /// How long to think about a move: a share of the remaining time plus
+    /// most of what comes back after the move, never more than half of
+    /// what is left
+    pub fn budget(&self) -> Duration {
+        let budget = self.remaining / 20 + self.delay + self.increment * 3 / 4;
+        budget.min(self.remaining / 2 + self.delay)
+    }
+}
+
+/// Formats a clock like `4:05`, with tenths of a second under ten seconds
+pub fn format_time(time: Duration) -> String {
+    if time < Duration::from_secs(10) {
+        format!("{:.1}", time.as_secs_f64())
+    } else {
+        let secs = time.as_secs();
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            o: control.base,
+            x: control.base,
+            running: None,
+        }
+    }
+
+    fn stored(&mut self, piece: Piece) -> &mut Duration {
+        if piece == Piece::O { &mut self.o } else { &mut self.x }
+    }
+
+    /// Time `piece` has used on this turn, after the delay
+    fn used(&self, piece: Piece) -> Duration {
+        match self.running {
+            Some((running, since)) if running == piece => since.elapsed().saturating_sub(self.control.delay),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Time left on `piece`'s clock, counting the turn in progress
+    pub fn remaining(&self, piece: Piece) -> Duration {
+        let stored = if piece == Piece::O { self.o } else { self.x };
+        stored.saturating_sub(self.used(piece))
+    }
+
+    pub fn time_left(&self, piece: Piece) -> TimeLeft {
+        TimeLeft {
+            remaining: self.remaining(piece),
+            increment: self.control.increment,
+            delay: self.control.delay,
+        }
+    }
+
+    pub fn is_flagged(&self, piece: Piece) -> bool {
+        self.remaining(piece).is_zero()
+    }
+
+    /// Starts `piece`'s clock, unless it is already running. Time the other
+    /// side used since its clock started is charged to it.
+    pub fn start(&mut self, piece: Piece) {
+        match self.running {
+            Some((running, _)) if running == piece => return,
+            Some((running, _)) => self.stop(running),
+            None => {}
+        }
+        self.running = Some((piece, Instant::now()));
+    }
+
+    fn stop(&mut self, piece: Piece) {
+        let remaining = self.remaining(piece);
+        *self.stored(piece) = remaining;
+        self.running = None;
+    }
+
+    /// Stops `piece`'s clock after it moved, adding the increment if the
+    /// move was made in time
+    pub fn press(&mut self, piece: Piece) {
+        self.stop(piece);
+        if !self.is_flagged(piece) {
+            let increment = self.control.increment;
+            *self.stored(piece) += increment;
+        }
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "O {}  X {}",
+            format_time(self.remaining(Piece::O)),
+            format_time(self.remaining(Piece::X))
+        )
+    }
+}
+
+#[cfg(test)]
+mod clock_tests {
+    use std::thread;
+
+    use super::*;
+    use crate::engine::Engine;
+    use crate::record::GameResult;
+    use crate::human::Human;
+    use crate::{Action, Game, Player};
+
+    #[test]
+    fn parses_time_controls() {
+        let control: TimeControl = "3+2".parse().unwrap();
+        assert_eq!(control.base, Duration::from_secs(180));
+        assert_eq!(control.increment, Duration::from_secs(2));
+        assert_eq!(control.to_string(), "3+2");
+
+        assert_eq!("1d5".parse::<TimeControl>().unwrap().delay, Duration::from_secs(5));
+        assert_eq!("0.5".parse::<TimeControl>().unwrap().base, Duration::from_secs(30));
+        assert!("0+2".parse::<TimeControl>().is_err());
+        assert!("five".parse::<TimeControl>().is_err());
+        assert_eq!("1e18".parse::<TimeControl>(), Err("Times must be at most a week"));
+        assert_eq!("1e30".parse::<TimeControl>(), Err("Times must be positive numbers"));
+        assert_eq!("1+1e18".parse::<TimeControl>(), Err("Times must be at most a week"));
+        assert_eq!("-1".parse::<TimeControl>(), Err("Times must be positive numbers"));
+    }
+
+    #[test]
+    fn only_the_side_to_move_loses_time() {
+        let mut clock = Clock::new(TimeControl {
+            base: Duration::from_millis(200),
+            increment: Duration::from_millis(500),
+            delay: Duration::ZERO,
+        });
+
+        clock.start(Piece::O);
+        thread::sleep(Duration::from_millis(50));
+        clock.press(Piece::O);
+        assert!(clock.remaining(Piece::O) > Duration::from_millis(500));
+        assert_eq!(clock.remaining(Piece::X), Duration::from_millis(200));
+
+        // No increment once the flag has fallen
+        clock.start(Piece::X);
+        thread::sleep(Duration::from_millis(250));
+        assert!(clock.is_flagged(Piece::X));
+        clock.press(Piece::X);
+        assert!(clock.is_flagged(Piece::X));
+    }
+
+    #[test]
+    fn delay_is_free_time() {
+        let mut clock = Clock::new(TimeControl {
+            base: Duration::from_secs(1),
+            increment: Duration::ZERO,
+            delay: Duration::from_secs(10),
+        });
+
+        clock.start(Piece::O);
+        thread::sleep(Duration::from_millis(20));
+        clock.press(Piece::O);
+
+        assert_eq!(clock.remaining(Piece::O), Duration::from_secs(1));
+        assert_eq!(format_time(clock.remaining(Piece::O)), "1.0");
+    }
+
+    #[test]
+    fn running_out_of_time_loses_the_game() {
+        let engine = || Player::Engine(Engine { depth: 2, ..Engine::default() });
+        let mut game = Game::with_players(engine(), engine());
+        game.set_clock(TimeControl { base: Duration::from_millis(20), ..TimeControl::default() });
+
+        game.start_clock(Piece::O);
+        thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(game.play_out(), GameResult::TimeForfeit(Piece::O));
+        assert!(game.record().to_string().contains("result=o-time"));
+    }
+
+    #[test]
+    fn timed_games_cannot_be_saved() {
+        let path = std::env::temp_dir().join(format!("timed-{}.json", std::process::id()));
+        let mut game = Game::with_players(Player::Human(Human), Player::Human(Human));
+        game.set_clock(TimeControl::default());
+
+        let status = game.apply(Action::Save(path.display().to_string()));
+
+        assert_eq!(status.as_deref(), Some("That is not possible in a timed game"));
+        assert!(!path.exists());
+    }
+}
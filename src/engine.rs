@@ -1,6 +1,356 @@
-use crate::{Agent, board};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-pub struct Engine;
+use crate::clock::TimeLeft;
+use crate::{Action, Agent, board};
+
+const SEARCH_DEPTH: u32 = 10;
+/// Evals at or beyond this size mean a forced win or loss
+pub const WIN_SCORE: isize = 100_000_000;
+const INFINITY: isize = 100_000_000_000;
+/// How many nodes are searched between looks at the clock and stop flag
+const CHECK_INTERVAL: u64 = 1024;
+/// One more than the most moves a game can have, for tables by ply
+const MAX_PLY: usize = 43;
+/// Buckets in the table of best moves
+const HASH_MOVE_BUCKETS: usize = 131_071;
+/// Buckets in the table of bounds MTD(f) keeps, fewer since they are bigger
+const BOUNDS_BUCKETS: usize = 262_139;
+/// What a window of four with only one side's pieces in it is worth, by
+/// how many pieces it has
+pub const EVAL_WEIGHTS: [isize; 4] = [1, 10, 100, 1000];
+
+#[derive(Clone, Debug)]
+pub struct Engine {
+    pub depth: u32,
+    /// When set, moves are searched one ply deeper at a time until this
+    /// much time has passed, up to `depth`
+    pub movetime: Option<Duration>,
+    pub ordering: MoveOrdering,
+    pub driver: Driver,
+    /// Used in place of [`EVAL_WEIGHTS`], for comparing evals in matches
+    pub weights: [isize; 4],
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine {
+            depth: SEARCH_DEPTH,
+            movetime: None,
+            ordering: MoveOrdering::default(),
+            driver: Driver::default(),
+            weights: EVAL_WEIGHTS,
+        }
+    }
+}
+
+/// How the search at each depth finds the eval of the root
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Driver {
+    /// One search with the full window
+    #[default]
+    AlphaBeta,
+    /// MTD(f): null-window searches around a guess, each telling whether
+    /// the eval is above or below it, until the bounds meet. Every search
+    /// is cheap because the window cuts off almost everything, and the
+    /// bounds each one proves are kept for the next.
+    Mtdf,
+}
+
+impl fmt::Display for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Driver::AlphaBeta => write!(f, "alphabeta"),
+            Driver::Mtdf => write!(f, "mtdf"),
+        }
+    }
+}
+
+impl FromStr for Driver {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alphabeta" => Ok(Driver::AlphaBeta),
+            "mtdf" => Ok(Driver::Mtdf),
+            _ => Err("Driver must be alphabeta or mtdf"),
+        }
+    }
+}
+
+/// Which of what the search learns is used to try the best moves first,
+/// on top of [`Engine::order_moves`]. Good orders cut off more of the tree
+/// without changing the result, so they can be compared by node counts.
+///
+/// By default only the hash move is used. Killers and history put moves
+/// ahead of ones that make three in a row, which searched more nodes on
+/// the positions they were tried on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveOrdering {
+    /// The best move found for a position earlier, even at a lower depth
+    pub hash_move: bool,
+    /// Moves that caused a cutoff at the same ply elsewhere in the tree
+    pub killers: bool,
+    /// Moves that caused cutoffs anywhere, weighted by depth
+    pub history: bool,
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        MoveOrdering {
+            hash_move: true,
+            killers: false,
+            history: false,
+        }
+    }
+}
+
+impl MoveOrdering {
+    /// Only the static order of [`Engine::order_moves`]
+    pub const STATIC: MoveOrdering = MoveOrdering {
+        hash_move: false,
+        killers: false,
+        history: false,
+    };
+}
+
+impl fmt::Display for MoveOrdering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = [(self.hash_move, "hash"), (self.killers, "killers"), (self.history, "history")]
+            .into_iter()
+            .filter_map(|(on, name)| on.then_some(name))
+            .collect();
+
+        if names.is_empty() { write!(f, "static") } else { write!(f, "{}", names.join(",")) }
+    }
+}
+
+impl FromStr for MoveOrdering {
+    type Err = &'static str;
+
+    /// Parses `static` or a comma separated list of `hash`, `killers` and
+    /// `history`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ordering = MoveOrdering::STATIC;
+        if s == "static" {
+            return Ok(ordering);
+        }
+
+        for name in s.split(',').map(str::trim) {
+            match name {
+                "hash" => ordering.hash_move = true,
+                "killers" => ordering.killers = true,
+                "history" => ordering.history = true,
+                _ => return Err("Move ordering must be static or a list of hash, killers and history"),
+            }
+        }
+
+        Ok(ordering)
+    }
+}
+
+/// The evaluation of playing in a single column, from the point of view of
+/// the piece to move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColumnScore {
+    pub col: usize,
+    pub eval: isize,
+    pub exact: bool,
+}
+
+/// A suggested move together with the reason it was chosen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hint {
+    pub col: usize,
+    pub reason: HintReason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HintReason {
+    /// The move completes four in a row
+    Win,
+    /// The move stops the opponent from completing four in a row
+    Block,
+    /// The opponent threatens to win in more than one column, so the game
+    /// is lost whatever is played
+    Lost,
+    /// The move had the best score in the search
+    BestEval(isize),
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let col = self.col + 1;
+        match self.reason {
+            HintReason::Win => write!(f, "Play column {col}, it wins the game"),
+            HintReason::Block => write!(f, "Play column {col}, it blocks a winning threat"),
+            HintReason::Lost => write!(f, "Play column {col}, though the opponent has another winning threat"),
+            HintReason::BestEval(eval) if eval >= WIN_SCORE => {
+                write!(f, "Play column {col}, it leads to a forced win")
+            }
+            HintReason::BestEval(eval) => write!(f, "Play column {col}, it has the best eval ({eval})"),
+        }
+    }
+}
+
+/// When a search should end. With neither limit set the engine's own depth
+/// is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+}
+
+/// The result of one finished iteration of a search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub best_move: usize,
+    /// From the point of view of the piece to move
+    pub eval: isize,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl SearchInfo {
+    /// Plies until the game is won or lost, if the eval shows a forced result
+    pub fn plies_to_end(&self) -> Option<u32> {
+        let left = (self.eval.abs() - WIN_SCORE) as u32;
+        (self.eval.abs() >= WIN_SCORE).then(|| self.depth.saturating_sub(left))
+    }
+}
+
+/// Shared state of a running search, used to stop it in the middle and to
+/// carry what it learns about move order from one node, and one depth, to
+/// the next
+struct Context<'a> {
+    deadline: Option<Instant>,
+    stop: Option<&'a AtomicBool>,
+    nodes: u64,
+    aborted: bool,
+    /// Best moves by position key
+    hash_moves: Table<u8>,
+    /// The last two moves that caused a cutoff, by number of moves played
+    killers: [[Option<usize>; 2]; MAX_PLY],
+    /// Cutoff counts weighted by depth, by piece and column
+    history: [[u64; 7]; 2],
+    /// What is known about evals by position key, at the depth in the
+    /// table, which spares MTD(f) searching the same positions again on
+    /// every pass
+    bounds: Table<Bounds>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Bounds {
+    /// Evals are well within 32 bits, and the smallest and largest values
+    /// stand for no bound, so more of them fit in the table
+    lower: i32,
+    upper: i32,
+    /// The move that proved the lower bound
+    best_move: u8,
+}
+
+impl Bounds {
+    const UNKNOWN: Bounds = Bounds { lower: i32::MIN, upper: i32::MAX, best_move: 0 };
+
+    fn lower(&self) -> isize {
+        if self.lower == i32::MIN { -INFINITY } else { self.lower as isize }
+    }
+
+    fn upper(&self) -> isize {
+        if self.upper == i32::MAX { INFINITY } else { self.upper as isize }
+    }
+}
+
+/// A fixed number of slots for what is known about positions, so a long
+/// search cannot use up the memory. Each key has a bucket of two slots,
+/// picked by its remainder by the number of buckets. Entries from deeper
+/// searches are worth more, so the first slot keeps the deepest entry,
+/// and the second takes the latest one that did not make it into the
+/// first. Keys are kept with their entries to tell apart positions that
+/// share a bucket.
+struct Table<T> {
+    /// Empty until the first entry, so searches that never use the table
+    /// do not pay for it. Slots nothing was put in have key 0, which no
+    /// position has.
+    buckets: Vec<[Entry<T>; 2]>,
+    size: usize,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Entry<T> {
+    key: u64,
+    depth: u32,
+    value: T,
+}
+
+impl<T: Copy + Default> Table<T> {
+    /// A table of `size` buckets, best a prime so every bit of the keys
+    /// plays a part in the bucket
+    fn new(size: usize) -> Table<T> {
+        Table { buckets: Vec::new(), size }
+    }
+
+    fn bucket(&self, key: u64) -> usize {
+        (key % self.size as u64) as usize
+    }
+
+    /// The entry for `key` and the depth it was searched to
+    fn get(&self, key: u64) -> Option<(T, u32)> {
+        let bucket = self.buckets.get(self.bucket(key))?;
+        bucket.iter().find(|entry| entry.key == key).map(|entry| (entry.value, entry.depth))
+    }
+
+    fn insert(&mut self, key: u64, depth: u32, value: T) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![[Entry::default(); 2]; self.size];
+        }
+        let bucket = self.bucket(key);
+        let [deepest, latest] = &mut self.buckets[bucket];
+        let entry = Entry { key, depth, value };
+
+        if deepest.key == key || deepest.depth <= depth {
+            // The entry it replaces is still the latest for its position
+            if deepest.key != key {
+                *latest = *deepest;
+            }
+            *deepest = entry;
+        } else {
+            *latest = entry;
+        }
+    }
+}
+
+impl Context<'_> {
+    fn unlimited() -> Context<'static> {
+        Context {
+            deadline: None,
+            stop: None,
+            nodes: 0,
+            aborted: false,
+            hash_moves: Table::new(HASH_MOVE_BUCKETS),
+            killers: [[None; 2]; MAX_PLY],
+            history: [[0; 7]; 2],
+            bounds: Table::new(BOUNDS_BUCKETS),
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.aborted && self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.check();
+        }
+
+        self.aborted
+    }
+
+    fn check(&mut self) {
+        let timed_out = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        let stopped = self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed));
+        self.aborted = timed_out || stopped;
+    }
+}
 
 struct GameState {
     eval: isize,
@@ -18,61 +368,277 @@ impl GameState {
 }
 
 impl Agent for Engine {
-    fn make_move(&self, board: &mut board::Board, piece: board::Piece) {
-        let mut board_clone = board.clone();
+    fn make_move(&mut self, board: &mut board::Board, _piece: board::Piece) -> Action {
         println!("Chosing a move...");
-        let game_state = self.negmax(&mut board_clone, piece, -100_000_000_000, 100_000_000_000, 10);
-        println!("Eval: {}", game_state.eval);
+        let (col, eval) = self.best_move(board);
+        println!("Eval: {eval}");
 
-        board
-            .insert_piece(game_state.best_move, piece)
-            .expect("failed to make a move");
+        board.eval = eval;
+
+        Action::Move(col)
+    }
 
-        board.eval = game_state.eval;
+    fn set_time(&mut self, time: &TimeLeft) {
+        self.movetime = Some(time.budget());
     }
 }
 
 impl Engine {
+    /// Searches for the best column for the piece to move, returning it
+    /// together with its eval
+    pub fn best_move(&self, board: &board::Board) -> (usize, isize) {
+        if let Some(movetime) = self.movetime {
+            let limits = Limits { depth: Some(self.depth), movetime: Some(movetime) };
+            let info = self.search(board, &limits, &AtomicBool::new(false), |_| {});
+            return (info.best_move, info.eval);
+        }
+
+        let mut board = board.clone();
+        let game_state = self.search_root(&mut board, self.depth, 0, &mut Context::unlimited());
+
+        (game_state.best_move, game_state.eval)
+    }
+
+    /// Like [`Engine::best_move`] without a move time, but gives up and
+    /// returns `None` as soon as `stop` is set
+    pub fn best_move_until(&self, board: &board::Board, stop: &AtomicBool) -> Option<(usize, isize)> {
+        let mut board = board.clone();
+        let mut context = Context {
+            stop: Some(stop),
+            ..Context::unlimited()
+        };
+        let game_state = self.search_root(&mut board, self.depth, 0, &mut context);
+
+        (!context.aborted).then_some((game_state.best_move, game_state.eval))
+    }
+
+    /// Searches one ply deeper at a time until `limits` are reached or
+    /// `stop` is set, calling `report` after every finished depth. The
+    /// result of the deepest finished depth is returned.
+    ///
+    /// The first depth always finishes, so there is a move to play even
+    /// when the search is stopped straight away.
+    pub fn search(
+        &self,
+        board: &board::Board,
+        limits: &Limits,
+        stop: &AtomicBool,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> SearchInfo {
+        let start = Instant::now();
+        let mut board = board.clone();
+
+        let max_depth = match limits {
+            Limits { depth: Some(depth), .. } => *depth,
+            Limits { movetime: Some(_), .. } => board.empty_cells() as u32,
+            _ => self.depth,
+        }
+        .max(1);
+
+        let mut context = Context::unlimited();
+        let mut best: Option<SearchInfo> = None;
+
+        for depth in 1..=max_depth {
+            // The last depth's eval is the best guess for MTD(f)
+            let guess = best.map_or(0, |info| info.eval);
+            let state = self.search_root(&mut board, depth, guess, &mut context);
+            if context.aborted {
+                break;
+            }
+
+            let info = SearchInfo {
+                depth,
+                best_move: state.best_move,
+                eval: state.eval,
+                nodes: context.nodes,
+                time: start.elapsed(),
+            };
+            report(&info);
+            best = Some(info);
+
+            // Deeper searches cannot change a forced result or a full board
+            if info.eval.abs() >= WIN_SCORE || depth as usize >= board.empty_cells() {
+                break;
+            }
+
+            context.deadline = limits.movetime.map(|movetime| start + movetime);
+            context.stop = Some(stop);
+            context.check();
+            if context.aborted {
+                break;
+            }
+        }
+
+        best.expect("the first depth is never stopped")
+    }
+
+    /// Scores every legal column for the piece to move and returns them
+    /// ranked from best to worst.
+    ///
+    /// A score is marked as exact when the search reached the end of every
+    /// line, either because the board fills up within the search depth or
+    /// because the column leads to a forced win or loss.
+    pub fn analyze_all(&self, board: &board::Board) -> Vec<ColumnScore> {
+        self.analyze_until(board, None).expect("an analysis without a deadline always finishes")
+    }
+
+    /// Like [`Engine::analyze_all`], but gives up and returns `None` if
+    /// `deadline` passes before every column is scored
+    pub fn analyze_until(&self, board: &board::Board, deadline: Option<Instant>) -> Option<Vec<ColumnScore>> {
+        if board.is_terminal().is_some() {
+            return Some(Vec::new());
+        }
+
+        let mut board = board.clone();
+        let piece = board.to_move();
+        let mut scores = Vec::new();
+        // The columns share their tables, which also saves allocating them
+        // for every column
+        let mut context = Context { deadline, ..Context::unlimited() };
+
+        for col in Engine::order_moves(&mut board, piece) {
+            if board.insert_piece(col, piece).is_err() {
+                continue;
+            }
+            let depth = self.depth.saturating_sub(1);
+            let child = self.negmax(&mut board, piece.opponent(), -INFINITY, INFINITY, depth, &mut context);
+            if context.aborted {
+                return None;
+            }
+            let eval = -child.eval;
+            let exact = board.empty_cells() <= depth as usize || eval.abs() >= WIN_SCORE;
+
+            board.undo_move(col);
+
+            scores.push(ColumnScore { col, eval, exact });
+        }
+
+        scores.sort_by_key(|score| -score.eval);
+
+        Some(scores)
+    }
+
+    /// Suggests a move for the piece to move, or `None` if the game is over.
+    ///
+    /// Immediate wins are preferred over blocking the opponent, and the
+    /// search is only used when neither is available. When there are two
+    /// threats to block the hint still names one, but says the game is
+    /// lost.
+    pub fn hint(&self, board: &board::Board) -> Option<Hint> {
+        if board.is_terminal().is_some() {
+            return None;
+        }
+
+        let piece = board.to_move();
+
+        if let Some(&col) = board.winning_moves(piece).first() {
+            return Some(Hint { col, reason: HintReason::Win });
+        }
+        match board.winning_moves(piece.opponent())[..] {
+            [col] => return Some(Hint { col, reason: HintReason::Block }),
+            [col, _, ..] => return Some(Hint { col, reason: HintReason::Lost }),
+            [] => {}
+        }
+
+        self.analyze_all(board).first().map(|score| Hint {
+            col: score.col,
+            reason: HintReason::BestEval(score.eval),
+        })
+    }
+
+    /// Searches the root to `depth` with [`Engine::driver`]
+    fn search_root(&self, board: &mut board::Board, depth: u32, guess: isize, context: &mut Context) -> GameState {
+        let piece = board.to_move();
+
+        match self.driver {
+            Driver::AlphaBeta => self.negmax(board, piece, -INFINITY, INFINITY, depth, context),
+            Driver::Mtdf => self.mtdf(board, piece, guess, depth, context),
+        }
+    }
+
+    /// Narrows the bounds on the eval with null-window searches starting at
+    /// `guess`. A search that fails high proves the eval is at least what
+    /// it returned, and its best move reaches that, so the move of the last
+    /// one is played.
+    fn mtdf(&self, board: &mut board::Board, piece: board::Piece, guess: isize, depth: u32, context: &mut Context) -> GameState {
+        let (mut lower, mut upper) = (-INFINITY, INFINITY);
+        let mut state = GameState { eval: guess, best_move: 0 };
+        let mut best_move = None;
+
+        while lower < upper {
+            let beta = if state.eval == lower { state.eval + 1 } else { state.eval };
+            state = self.negmax(board, piece, beta - 1, beta, depth, context);
+            if context.aborted {
+                break;
+            }
+
+            if state.eval < beta {
+                upper = state.eval;
+            } else {
+                lower = state.eval;
+                best_move = Some(state.best_move);
+            }
+        }
+
+        GameState {
+            eval: state.eval,
+            best_move: best_move.unwrap_or(state.best_move),
+        }
+    }
+
     fn negmax(
         &self,
         board: &mut board::Board,
         piece: board::Piece,
         mut alpha: isize,
-        beta: isize,
+        mut beta: isize,
         depth: u32,
+        context: &mut Context,
     ) -> GameState {
+        context.nodes += 1;
+        if context.should_stop() {
+            return GameState { eval: 0, best_move: 0 };
+        }
 
         if let Some(result) = board.is_terminal() {
-            match result {
-                board::Piece::O => {
-                    return GameState {
-                        eval: 100_000_000 + depth as isize,
-                        best_move: 0,
-                    };
-                }
-                board::Piece::X => {
-                    return GameState {
-                        eval: -100_000_000 - depth as isize,
-                        best_move: 0,
-                    };
-                }
-                board::Piece::Empty => {
-                    return GameState {
-                        eval: 0,
-                        best_move: 0,
-                    };
-                }
+            // Scores are from the point of view of the piece to move
+            let eval = if result == board::Piece::Empty {
+                0
+            } else if result == piece {
+                WIN_SCORE + depth as isize
+            } else {
+                -WIN_SCORE - depth as isize
             };
+
+            return GameState { eval, best_move: 0 };
         }
 
         if depth == 0 {
             return GameState {
-                eval: Engine::eval(board, piece),
+                eval: Engine::eval_weighted(board, piece, &self.weights),
                 best_move: 0,
             };
         }
 
-        let moves = Engine::order_moves(board, piece);
+        // Only MTD(f) keeps bounds, so plain alpha-beta searches exactly as
+        // it always has
+        let window = (alpha, beta);
+        if self.driver == Driver::Mtdf
+            && let Some((known, _)) = context.bounds.get(board.key()).filter(|&(_, known_depth)| known_depth == depth)
+        {
+            let best_move = usize::from(known.best_move);
+            if known.lower() >= beta {
+                return GameState { eval: known.lower(), best_move };
+            }
+            if known.upper() <= alpha {
+                return GameState { eval: known.upper(), best_move };
+            }
+            alpha = alpha.max(known.lower());
+            beta = beta.min(known.upper());
+        }
+
+        let mut moves = Engine::order_moves(board, piece);
+        self.reorder(&mut moves, board, piece, context);
 
         let mut best_eval = isize::MIN;
         let mut best_move = 0;
@@ -81,7 +647,7 @@ impl Engine {
             if board.insert_piece(current_move, piece).is_err() {
                 continue;
             }
-            let child = self.negmax(board, piece.opponent(), -beta, -alpha, depth - 1);
+            let child = self.negmax(board, piece.opponent(), -beta, -alpha, depth - 1, context);
             let score = -child.eval;
 
             board.undo_move(current_move);
@@ -93,24 +659,48 @@ impl Engine {
 
             alpha = alpha.max(best_eval);
             if alpha >= beta {
+                self.record_cutoff(current_move, board, piece, depth, context);
                 break;
             }
         }
 
+        // An aborted search may not have tried the best move
+        if self.ordering.hash_move && !context.aborted {
+            context.hash_moves.insert(board.key(), depth, best_move as u8);
+        }
+        if self.driver == Driver::Mtdf && !context.aborted {
+            let key = board.key();
+            let mut known = match context.bounds.get(key) {
+                Some((known, known_depth)) if known_depth == depth => known,
+                _ => Bounds { best_move: best_move as u8, ..Bounds::UNKNOWN },
+            };
+            if best_eval > window.0 {
+                known.lower = best_eval as i32;
+                known.best_move = best_move as u8;
+            }
+            if best_eval < window.1 {
+                known.upper = best_eval as i32;
+            }
+            context.bounds.insert(key, depth, known);
+        }
+
         GameState {
             eval: best_eval,
-            best_move: best_move,
+            best_move,
         }
     }
 
+    /// Moves that make three in a row first, then the ones closest to the
+    /// center
     pub fn order_moves(board: &mut board::Board, piece: board::Piece) -> Vec<usize> {
         let mut moves = board.get_moves();
-        let center = 4;
+        // Columns count from 0, so the middle one is 3
+        let center = 3;
 
         moves.sort_by_key(|&col| {
             let mut score = 0;
             if board.creates_three_in_a_row(col, piece) { score += 1000 }
-            score += 100 - (col as isize - center as isize).abs() as isize;
+            score += 100 - (col as isize - center as isize).abs();
             -score
         }
         );
@@ -118,15 +708,56 @@ impl Engine {
         moves
     }
 
+    /// Moves the hash move to the front, then the killers, then sorts the
+    /// rest by history, as far as [`Engine::ordering`] allows. Ties keep
+    /// the static order.
+    fn reorder(&self, moves: &mut [usize], board: &board::Board, piece: board::Piece, context: &Context) {
+        let ordering = self.ordering;
+        if ordering == MoveOrdering::STATIC {
+            return;
+        }
+
+        let hash_move = ordering.hash_move.then(|| context.hash_moves.get(board.key())).flatten().map(|(col, _)| usize::from(col));
+        let killers = if ordering.killers { context.killers[board.move_count()] } else { [None; 2] };
+        let history = &context.history[piece as usize];
+
+        moves.sort_by_key(|&col| {
+            let rank = if Some(col) == hash_move {
+                0
+            } else if killers.contains(&Some(col)) {
+                1
+            } else {
+                2
+            };
+            let score = if ordering.history { history[col] } else { 0 };
+            (rank, std::cmp::Reverse(score))
+        });
+    }
+
+    /// Remembers a move that caused a beta cutoff
+    fn record_cutoff(&self, col: usize, board: &board::Board, piece: board::Piece, depth: u32, context: &mut Context) {
+        if self.ordering.killers {
+            let killers = &mut context.killers[board.move_count()];
+            if killers[0] != Some(col) {
+                killers[1] = killers[0];
+                killers[0] = Some(col);
+            }
+        }
+        if self.ordering.history {
+            context.history[piece as usize][col] += u64::from(depth * depth);
+        }
+    }
+
     pub fn eval(board: &board::Board, piece: board::Piece) -> isize {
+        Engine::eval_weighted(board, piece, &EVAL_WEIGHTS)
+    }
+
+    /// Like [`Engine::eval`], with `weights` in place of [`EVAL_WEIGHTS`]
+    pub fn eval_weighted(board: &board::Board, piece: board::Piece, weights: &[isize; 4]) -> isize {
         let mut eval: isize = 0;
 
         if let Some(result) = board.check_win() {
-            return match result {
-                board::Piece::O => 100_000_000,
-                board::Piece::X => 100_000_000,
-                board::Piece::Empty => 0,
-            };
+            return if result == piece { WIN_SCORE } else { -WIN_SCORE };
         }
 
         let (diagonals_up, diagonals_down) = board.diagonals();
@@ -140,17 +771,14 @@ impl Engine {
             .chain(rows);
 
         for line in lines {
-            match piece {
-                board::Piece::O => eval += Engine::evaluate_line(&line, piece),
-                board::Piece::X => eval -= Engine::evaluate_line(&line, piece),
-                _ => panic!("Invalid player"),
-            }
+            eval += Engine::evaluate_line(&line, piece, weights);
+            eval -= Engine::evaluate_line(&line, piece.opponent(), weights);
         }
 
         eval
     }
 
-    fn evaluate_line(line: &[board::Piece], piece: board::Piece) -> isize {
+    fn evaluate_line(line: &[board::Piece], piece: board::Piece, weights: &[isize; 4]) -> isize {
         let mut eval: isize = 0;
 
         for window in line.windows(4) {
@@ -159,8 +787,9 @@ impl Engine {
 
             if count_pieces + count_empty == 4 {
                 // window has only your own pieces and empty spaces
-                // evaled exponentially with how many pieces you have filled
-                eval += 10_isize.pow(count_pieces as u32)
+                // evaled by how many pieces you have filled, exponentially
+                // with the default weights
+                eval += weights[count_pieces]
             } else {
                 // window is blocked, evaled to 0
                 eval += 0;
@@ -171,3 +800,162 @@ impl Engine {
         eval
     }
 }
+
+#[cfg(test)]
+mod engine_tests {
+    use super::*;
+    use board::Board;
+
+    fn play(moves: &[usize]) -> Board {
+        let mut board = Board::new();
+        for &col in moves {
+            let piece = board.to_move();
+            board.insert_piece(col, piece).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn moves_are_ordered_out_from_the_middle() {
+        let mut board = Board::new();
+
+        assert_eq!(Engine::order_moves(&mut board, board::Piece::O), [3, 2, 4, 1, 5, 0, 6]);
+    }
+
+    #[test]
+    fn analyze_all_ranks_winning_move_first() {
+        // O has three in the bottom row and can win in column 3
+        let board = play(&[0, 0, 1, 1, 2, 2]);
+
+        let scores = Engine { depth: 4, ..Engine::default() }.analyze_all(&board);
+
+        assert_eq!(scores.len(), 7);
+        assert_eq!(scores[0].col, 3);
+        assert!(scores[0].exact);
+        assert!(scores[0].eval >= WIN_SCORE);
+        assert!(scores.windows(2).all(|w| w[0].eval >= w[1].eval));
+    }
+
+    #[test]
+    fn hint_prefers_win_over_block() {
+        // O to move with three in the bottom row
+        let board = play(&[0, 0, 1, 1, 2, 2]);
+
+        let hint = Engine { depth: 4, ..Engine::default() }.hint(&board).unwrap();
+
+        assert_eq!(hint, Hint { col: 3, reason: HintReason::Win });
+    }
+
+    #[test]
+    fn hint_blocks_opponent_threat() {
+        let board = play(&[0, 6, 1, 6, 2]);
+
+        let hint = Engine { depth: 4, ..Engine::default() }.hint(&board).unwrap();
+
+        assert_eq!(hint, Hint { col: 3, reason: HintReason::Block });
+    }
+
+    #[test]
+    fn hint_admits_two_threats_are_lost() {
+        // O has an open three in the bottom row with both ends free
+        let board = play(&[1, 1, 2, 2, 3]);
+
+        let hint = Engine { depth: 4, ..Engine::default() }.hint(&board).unwrap();
+
+        assert_eq!(hint, Hint { col: 0, reason: HintReason::Lost });
+        assert!(hint.to_string().contains("another winning threat"));
+    }
+
+    #[test]
+    fn tables_keep_the_deepest_and_the_latest_entry_in_a_bucket() {
+        let mut table = Table::new(7);
+        table.insert(3, 2, 1);
+        table.insert(10, 1, 2);
+        assert_eq!((table.get(3), table.get(10)), (Some((1, 2)), Some((2, 1))));
+
+        // The latest slot goes to the newer entry
+        table.insert(17, 0, 3);
+        assert_eq!((table.get(3), table.get(10), table.get(17)), (Some((1, 2)), None, Some((3, 0))));
+
+        // A deeper entry takes the first slot, and the one it replaces
+        // becomes the latest
+        table.insert(24, 5, 4);
+        assert_eq!((table.get(3), table.get(17), table.get(24)), (Some((1, 2)), None, Some((4, 5))));
+
+        for key in 1..1000 {
+            table.insert(key, 9, 0);
+        }
+        assert_eq!(table.buckets.len(), 7);
+    }
+
+    #[test]
+    fn aborted_searches_store_no_hash_move() {
+        let mut board = board::Board::new();
+        let stop = AtomicBool::new(true);
+        let mut context = Context { stop: Some(&stop), ..Context::unlimited() };
+        // Let the search get past the first look at the stop flag
+        context.nodes = 1;
+
+        let engine = Engine::default();
+        engine.negmax(&mut board, board::Piece::O, -INFINITY, INFINITY, 12, &mut context);
+
+        assert!(context.aborted);
+        assert_eq!(context.hash_moves.get(board.key()), None);
+    }
+
+    #[test]
+    fn analyze_until_gives_up_at_the_deadline() {
+        let board = board::Board::new();
+        let engine = Engine { depth: 12, ..Engine::default() };
+
+        assert_eq!(engine.analyze_until(&board, Some(Instant::now())), None);
+        assert_eq!(Engine { depth: 2, ..engine }.analyze_until(&board, None).map(|scores| scores.len()), Some(7));
+    }
+
+    #[test]
+    fn analyze_all_skips_full_columns() {
+        let board = play(&[0, 0, 0, 0, 0, 0]);
+
+        let scores = Engine { depth: 4, ..Engine::default() }.analyze_all(&board);
+
+        assert_eq!(scores.len(), 6);
+        assert!(scores.iter().all(|score| score.col != 0));
+    }
+
+    #[test]
+    fn move_ordering_saves_nodes_without_changing_the_result() {
+        let board = play(&[3, 3, 4, 2]);
+        let limits = Limits { depth: Some(6), movetime: None };
+        let search = |ordering: &str| {
+            let engine = Engine { ordering: ordering.parse().unwrap(), ..Engine::default() };
+            engine.search(&board, &limits, &AtomicBool::new(false), |_| {})
+        };
+
+        let plain = search("static");
+        let full = search("hash,killers,history");
+        let hashed = search("hash");
+
+        assert_eq!((full.best_move, full.eval), (plain.best_move, plain.eval));
+        assert_eq!((hashed.best_move, hashed.eval), (plain.best_move, plain.eval));
+        assert!(hashed.nodes < plain.nodes);
+        assert_eq!(MoveOrdering::default().to_string(), "hash");
+        assert!("hash,nothing".parse::<MoveOrdering>().is_err());
+    }
+
+    #[test]
+    fn mtdf_agrees_with_alpha_beta() {
+        let limits = Limits { depth: Some(6), movetime: None };
+        for moves in [&[][..], &[3, 3, 4, 2], &[0, 0, 1, 1, 2, 2]] {
+            let board = play(moves);
+            let search = |driver| {
+                let engine = Engine { driver, ..Engine::default() };
+                engine.search(&board, &limits, &AtomicBool::new(false), |_| {})
+            };
+
+            let plain = search(Driver::AlphaBeta);
+            let mtdf = search(Driver::Mtdf);
+
+            assert_eq!((mtdf.best_move, mtdf.eval), (plain.best_move, plain.eval), "after {moves:?}");
+        }
+    }
+}
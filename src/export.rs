@@ -0,0 +1,312 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::board::{Board, Piece};
+use crate::raster::{Canvas, Rgb, Shape};
+
+const ROWS: usize = 6;
+const COLS: usize = 7;
+
+/// The largest cell size, which keeps images under 4000 pixels wide
+pub const MAX_CELL_SIZE: u32 = 512;
+
+const BOARD_COLOR: Rgb = Rgb(31, 79, 191);
+const HOLE_COLOR: Rgb = Rgb(240, 240, 240);
+const X_COLOR: Rgb = Rgb(214, 40, 40);
+const O_COLOR: Rgb = Rgb(247, 197, 49);
+const MARK_COLOR: Rgb = Rgb(34, 34, 34);
+const ARROW_COLOR: Rgb = Rgb(46, 160, 67);
+
+/// An arrow from one cell `(col, row)` to another, for pointing out plans
+/// and threats in reviews
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arrow {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+}
+
+/// What to draw on top of the pieces
+#[derive(Clone, Debug)]
+pub struct ImageOptions {
+    /// Width and height of a cell in pixels
+    pub cell_size: u32,
+    /// The moves that led to the board, used to number the pieces
+    pub moves: Option<Vec<usize>>,
+    pub last_move: bool,
+    pub winning_line: bool,
+    /// Dots in the empty cells where either side would complete four
+    pub threats: bool,
+    pub arrows: Vec<Arrow>,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            cell_size: 64,
+            moves: None,
+            last_move: true,
+            winning_line: true,
+            threats: false,
+            arrows: Vec::new(),
+        }
+    }
+}
+
+impl Arrow {
+    /// Parses cells written like chess squares, with the column as a letter
+    /// from `a` and the row as a number from 1 at the bottom: `a1-d4`
+    pub fn parse(s: &str) -> Result<Arrow, &'static str> {
+        let cell = |s: &str| {
+            let mut chars = s.chars();
+            let col = chars.next().filter(|c| ('a'..='g').contains(c));
+            let row = chars.as_str().parse::<usize>().ok().filter(|row| (1..=ROWS).contains(row));
+            match (col, row) {
+                (Some(col), Some(row)) => Ok((col as usize - 'a' as usize, row - 1)),
+                _ => Err("Cells must look like a1 to g6"),
+            }
+        };
+
+        let (from, to) = s.split_once('-').ok_or("Arrows must look like a1-d4")?;
+
+        Ok(Arrow {
+            from: cell(from)?,
+            to: cell(to)?,
+        })
+    }
+}
+
+pub(crate) fn piece_color(piece: Piece) -> Rgb {
+    match piece {
+        Piece::X => X_COLOR,
+        Piece::O => O_COLOR,
+        Piece::Empty => HOLE_COLOR,
+    }
+}
+
+/// Pixel position of the centre of a cell `(col, row)`
+pub(crate) fn cell_center((col, row): (usize, usize), size: f32) -> (f32, f32) {
+    ((col as f32 + 0.5) * size, ((ROWS - 1 - row) as f32 + 0.5) * size)
+}
+
+/// Lays out the board as shapes, which are then written as SVG or rasterised
+pub fn shapes(board: &Board, options: &ImageOptions) -> Vec<Shape> {
+    let size = options.cell_size as f32;
+    let center = |cell| cell_center(cell, size);
+
+    let mut shapes = vec![Shape::Rect {
+        x: 0.0,
+        y: 0.0,
+        w: size * COLS as f32,
+        h: size * ROWS as f32,
+        color: BOARD_COLOR,
+    }];
+
+    for col in 0..COLS {
+        for row in 0..ROWS {
+            let (cx, cy) = center((col, row));
+            let color = piece_color(board.get(col, row));
+            shapes.push(Shape::Circle { cx, cy, r: size * 0.4, color });
+        }
+    }
+
+    if let Some(moves) = &options.moves {
+        let mut heights = [0; COLS];
+        for (i, &col) in moves.iter().enumerate() {
+            if col >= COLS || heights[col] >= ROWS {
+                break;
+            }
+            let (x, y) = center((col, heights[col]));
+            heights[col] += 1;
+
+            let color = if board.get(col, heights[col] - 1) == Piece::X {
+                Rgb(255, 255, 255)
+            } else {
+                MARK_COLOR
+            };
+            let text = (i + 1).to_string();
+            shapes.push(Shape::Text { x, y, size: size * 0.3, text, color });
+        }
+    }
+
+    if options.threats {
+        for (piece, offset) in [(Piece::X, -0.12), (Piece::O, 0.12)] {
+            for cell in board.threats(piece) {
+                let (cx, cy) = center(cell);
+                let color = piece_color(piece);
+                shapes.push(Shape::Circle { cx: cx + offset * size, cy, r: size * 0.1, color });
+            }
+        }
+    }
+
+    if options.last_move
+        && let Some(cell) = board.last_cell()
+    {
+        let (cx, cy) = center(cell);
+        shapes.push(Shape::Ring { cx, cy, r: size * 0.4, width: size * 0.06, color: MARK_COLOR });
+    }
+
+    if options.winning_line
+        && let Some(line) = board.winning_line()
+    {
+        let (from, to) = (center(line[0]), center(line[3]));
+        shapes.push(Shape::Line { from, to, width: size * 0.1, color: MARK_COLOR });
+    }
+
+    for arrow in &options.arrows {
+        shapes.extend(arrow_shapes(center(arrow.from), center(arrow.to), size));
+    }
+
+    shapes
+}
+
+/// A shaft that stops short of the target cell's centre, and a head
+fn arrow_shapes(from: (f32, f32), to: (f32, f32), size: f32) -> Vec<Shape> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt().max(1.0);
+    let (ux, uy) = (dx / length, dy / length);
+
+    let head = size * 0.3;
+    let base = (to.0 - ux * head, to.1 - uy * head);
+    let half = head * 0.6;
+
+    vec![
+        Shape::Line { from, to: base, width: size * 0.1, color: ARROW_COLOR },
+        Shape::Triangle {
+            points: [to, (base.0 - uy * half, base.1 + ux * half), (base.0 + uy * half, base.1 - ux * half)],
+            color: ARROW_COLOR,
+        },
+    ]
+}
+
+/// Width and height of the image in pixels, or an error if the cell size
+/// is zero or more than [`MAX_CELL_SIZE`]
+pub fn image_size(options: &ImageOptions) -> Result<(u32, u32), &'static str> {
+    if !(1..=MAX_CELL_SIZE).contains(&options.cell_size) {
+        return Err("Cells must be from 1 to 512 pixels");
+    }
+    let side = |cells: usize| options.cell_size.checked_mul(cells as u32).ok_or("Image is too large");
+
+    Ok((side(COLS)?, side(ROWS)?))
+}
+
+pub fn to_svg(board: &Board, options: &ImageOptions) -> Result<String, &'static str> {
+    let (width, height) = image_size(options)?;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n"
+    );
+
+    for shape in shapes(board, options) {
+        let element = match shape {
+            Shape::Rect { x, y, w, h, color } => {
+                format!("<rect x=\"{x}\" y=\"{y}\" width=\"{w}\" height=\"{h}\" fill=\"{}\"/>", color.hex())
+            }
+            Shape::Circle { cx, cy, r, color } => {
+                format!("<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{r}\" fill=\"{}\"/>", color.hex())
+            }
+            Shape::Ring { cx, cy, r, width, color } => format!(
+                "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{r}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{width}\"/>",
+                color.hex()
+            ),
+            Shape::Line { from, to, width, color } => format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{width}\" stroke-linecap=\"round\"/>",
+                from.0,
+                from.1,
+                to.0,
+                to.1,
+                color.hex()
+            ),
+            Shape::Triangle { points, color } => {
+                let points: Vec<String> = points.iter().map(|(x, y)| format!("{x},{y}")).collect();
+                format!("<polygon points=\"{}\" fill=\"{}\"/>", points.join(" "), color.hex())
+            }
+            Shape::Text { x, y, size, text, color } => format!(
+                "<text x=\"{x}\" y=\"{y}\" font-family=\"sans-serif\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\">{text}</text>",
+                size * 1.4,
+                color.hex()
+            ),
+        };
+        svg.push_str("  ");
+        svg.push_str(&element);
+        svg.push('\n');
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+pub fn to_canvas(board: &Board, options: &ImageOptions) -> Result<Canvas, &'static str> {
+    let (width, height) = image_size(options)?;
+    let mut canvas = Canvas::new(width, height)?;
+    canvas.draw(&shapes(board, options));
+    Ok(canvas)
+}
+
+pub fn to_png(board: &Board, options: &ImageOptions) -> io::Result<Vec<u8>> {
+    let canvas = to_canvas(board, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut bytes = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut bytes, canvas.width, canvas.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&canvas.pixels)?;
+
+    Ok(bytes)
+}
+
+/// Writes a PNG if the file name ends in `.png` and an SVG otherwise
+pub fn save(board: &Board, options: &ImageOptions, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+
+    if path.extension().is_some_and(|ext| ext == "png") {
+        fs::write(path, to_png(board, options)?)
+    } else {
+        let svg = to_svg(board, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        fs::write(path, svg)
+    }
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::*;
+
+    #[test]
+    fn svg_has_a_disc_per_cell_and_numbers() {
+        let moves = vec![3, 3, 2];
+        let board = Board::from_moves(&moves).unwrap();
+        let options = ImageOptions {
+            moves: Some(moves),
+            ..ImageOptions::default()
+        };
+
+        let svg = to_svg(&board, &options).unwrap();
+
+        assert_eq!(svg.matches("<circle").count(), 42 + 1);
+        assert!(svg.contains(">3</text>"));
+    }
+
+    #[test]
+    fn png_is_encoded() {
+        let board = Board::from_moves(&[3]).unwrap();
+
+        let png = to_png(&board, &ImageOptions::default()).unwrap();
+
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+    #[test]
+    fn oversized_cells_are_rejected() {
+        let board = Board::new();
+        let options = ImageOptions { cell_size: u32::MAX, ..ImageOptions::default() };
+
+        assert_eq!(image_size(&options), Err("Cells must be from 1 to 512 pixels"));
+        assert!(to_png(&board, &options).is_err());
+        assert_eq!(image_size(&ImageOptions { cell_size: MAX_CELL_SIZE, ..options }), Ok((3584, 3072)));
+    }
+
+    #[test]
+    fn arrows_parse_cells() {
+        assert_eq!(Arrow::parse("a1-d4"), Ok(Arrow { from: (0, 0), to: (3, 3) }));
+        assert!(Arrow::parse("h1-a1").is_err());
+    }
+}
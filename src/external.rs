@@ -0,0 +1,328 @@
+//! Playing against another engine program that speaks the text protocol in
+//! [`crate::protocol`], such as another Connect Four engine or an earlier
+//! build of this one.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::board::{self, Board, Piece};
+use crate::clock::TimeLeft;
+use crate::{Action, Agent};
+
+/// How long an engine gets to start up and answer `uciok` and `readyok`
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Extra time on top of the move time before the engine is told to stop,
+/// and again before it loses for not answering
+const GRACE: Duration = Duration::from_secs(1);
+
+/// An engine running in a child process
+pub struct External {
+    /// The program and its arguments, as given to [`External::launch`]
+    pub command: Vec<String>,
+    /// How long the engine may think about each move
+    pub movetime: Duration,
+    /// Why the engine forfeited the game, if it did
+    pub error: Option<String>,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    moves: Vec<usize>,
+}
+
+fn timed_out(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("The engine did not answer {what} in time"))
+}
+
+/// Splits a command line into the program and its arguments at spaces.
+/// Single or double quotes keep spaces in a path or argument, like
+/// `"/opt/my engines/c4" engine`.
+pub fn split_command(command: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err("Unterminated quote in the engine command");
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+/// Joins a program and its arguments into a command line that
+/// [`split_command`] reads back the same way
+pub fn join_command(command: &[String]) -> String {
+    let quoted: Vec<String> = command
+        .iter()
+        .map(|word| {
+            if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+                word.clone()
+            } else if word.contains('"') {
+                format!("'{word}'")
+            } else {
+                format!("\"{word}\"")
+            }
+        })
+        .collect();
+
+    quoted.join(" ")
+}
+
+impl External {
+    /// Starts `command`, the program followed by its arguments, and waits
+    /// for it to finish the handshake
+    pub fn launch(command: &[String], movetime: Duration) -> io::Result<External> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No engine program given"))?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // Reading on a thread lets every wait for the engine have a timeout
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = External {
+            command: command.to_vec(),
+            movetime,
+            error: None,
+            child,
+            stdin,
+            lines,
+            moves: Vec::new(),
+        };
+
+        engine.send("uci")?;
+        engine.wait_for("uciok", STARTUP_TIMEOUT)?;
+        engine.send("isready")?;
+        engine.wait_for("readyok", STARTUP_TIMEOUT)?;
+
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{line}")?;
+        self.stdin.flush()
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {name} value {value}"))
+    }
+
+    /// Reads lines until one starting with `prefix`, which is returned
+    fn wait_for(&mut self, prefix: &str, timeout: Duration) -> io::Result<String> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(left) {
+                Ok(line) if line.starts_with(prefix) => return Ok(line),
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => return Err(timed_out(prefix)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The engine exited"));
+                }
+            }
+        }
+    }
+
+    /// Asks the engine for its move after `moves`. Engines that do not
+    /// answer in time are told to stop, and fail if they still do not
+    /// answer. Moves that are not legal are an error.
+    pub fn best_move(&mut self, moves: &[usize]) -> io::Result<usize> {
+        let board = Board::from_moves(moves).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        // Anything left over from a search that answered too late is stale
+        while self.lines.try_recv().is_ok() {}
+
+        self.send(&format!("position moves {}", board::format_moves(moves)))?;
+        self.send(&format!("go movetime {}", self.movetime.as_millis()))?;
+
+        let line = match self.wait_for("bestmove", self.movetime + GRACE) {
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                self.send("stop")?;
+                self.wait_for("bestmove", GRACE)?
+            }
+            result => result?,
+        };
+
+        let col = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|col| col.parse::<usize>().ok())
+            .and_then(|col| col.checked_sub(1));
+
+        match col {
+            Some(col) if board.get_moves().contains(&col) => Ok(col),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The engine played an illegal move: `{line}`"),
+            )),
+        }
+    }
+}
+
+impl Agent for External {
+    fn set_moves(&mut self, moves: &[usize]) {
+        self.moves = moves.to_vec();
+    }
+
+    /// Plays the engine's move, or resigns for it if it failed to give a
+    /// legal one in time
+    fn make_move(&mut self, _board: &mut Board, _piece: Piece) -> Action {
+        let moves = self.moves.clone();
+
+        match self.best_move(&moves) {
+            Ok(col) => Action::Move(col),
+            Err(e) => {
+                self.error = Some(e.to_string());
+                Action::Resign
+            }
+        }
+    }
+
+    fn set_time(&mut self, time: &TimeLeft) {
+        self.movetime = time.budget();
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        self.send("quit").ok();
+
+        let deadline = Instant::now() + GRACE;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod external_tests {
+    use super::*;
+    use crate::human::Human;
+    use crate::{Game, Player};
+    use crate::record::{self, GameRecord, GameResult, PlayerRecord};
+
+    /// A shell script that answers the handshake and plays `answer` to `go`
+    fn fake_engine(answer: &str) -> Vec<String> {
+        let script = format!(
+            "while read line; do case $line in \
+             uci) echo uciok;; isready) echo readyok;; go*) {answer};; quit) exit;; esac; done"
+        );
+        vec!["sh".to_string(), "-c".to_string(), script]
+    }
+
+    #[test]
+    fn plays_the_engines_move() {
+        let mut engine = External::launch(&fake_engine("echo bestmove 4"), Duration::from_millis(10)).unwrap();
+
+        assert_eq!(engine.best_move(&[3, 3]).unwrap(), 3);
+    }
+
+    #[test]
+    fn illegal_moves_forfeit() {
+        let mut engine = External::launch(&fake_engine("echo bestmove 1"), Duration::from_millis(10)).unwrap();
+        engine.set_moves(&[0, 0, 0, 0, 0, 0]);
+
+        let action = engine.make_move(&mut Board::from_moves(&[0, 0, 0, 0, 0, 0]).unwrap(), Piece::O);
+
+        assert!(matches!(action, Action::Resign));
+        assert!(engine.error.as_deref().unwrap().contains("illegal move"));
+    }
+
+    #[test]
+    fn forfeits_are_shown_by_the_game() {
+        let engine = External::launch(&fake_engine("echo bestmove 9"), Duration::from_millis(10)).unwrap();
+        let mut game = Game::with_players(Player::Human(Human), Player::External(engine));
+
+        let action = game.ask(Piece::O);
+        game.apply(action);
+
+        assert!(game.outcome().unwrap().starts_with("O forfeits: The engine played an illegal move"));
+    }
+
+    #[test]
+    fn commands_keep_quoted_spaces() {
+        let command = split_command(r#""/opt/my engines/c4" engine --name 'Big "Blue"' """#).unwrap();
+
+        assert_eq!(command, ["/opt/my engines/c4", "engine", "--name", r#"Big "Blue""#, ""]);
+        assert_eq!(split_command(&join_command(&command)).unwrap(), command);
+        assert!(split_command("\"/opt/c4 engine").is_err());
+    }
+
+    #[test]
+    fn loaded_games_never_run_engine_programs() {
+        let marker = std::env::temp_dir().join(format!("launched-{}", std::process::id()));
+        let record = GameRecord {
+            version: record::VERSION,
+            x: PlayerRecord::Human,
+            o: PlayerRecord::External { command: format!("touch {}", marker.display()) },
+            first: Piece::O,
+            moves: vec![3],
+            result: GameResult::Ongoing,
+            started: 0,
+            finished: None,
+        };
+
+        let game = Game::from_record(&record).unwrap();
+
+        assert!(!marker.exists());
+        assert_eq!(game.record().o, PlayerRecord::Human);
+    }
+
+    #[test]
+    fn silent_engines_time_out() {
+        let mut engine = External::launch(&fake_engine("true"), Duration::from_millis(10)).unwrap();
+
+        let error = engine.best_move(&[]).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
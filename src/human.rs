@@ -1,24 +1,135 @@
 use std::io;
 
-use crate::{Agent, board};
+use crate::engine::Engine;
+use crate::{Action, Agent, board};
 
 pub struct Human;
 
+/// What the human typed at the prompt
+pub enum Input {
+    Column(usize),
+    Command(Command),
+}
+
+pub enum Command {
+    Hint,
+    Eval,
+    Help,
+    Undo,
+    Redo,
+    Save(String),
+    Load(String),
+    Flip,
+    Resign,
+    Draw,
+    Chat(String),
+    Quit,
+}
+
+const HELP: &str = "\
+Commands:
+  1-7          drop a piece in that column
+  hint         suggest a move
+  eval         show the engine's score for every column
+  undo         take back your last move
+  redo         replay a move that was taken back
+  save <file>  save the game to a file
+  load <file>  load a game from a file
+  flip         swap sides with the other player
+  resign       give up the game
+  draw         offer the other player a draw
+  chat <text>  send a message to the other player
+  help         show this list
+  quit         leave without finishing the game";
+
+impl Command {
+    fn parse(s: &str) -> Option<Result<Command, &'static str>> {
+        let (name, arg) = match s.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (s, ""),
+        };
+
+        let command = match name {
+            "hint" => Command::Hint,
+            "eval" => Command::Eval,
+            "help" => Command::Help,
+            "undo" => Command::Undo,
+            "redo" => Command::Redo,
+            "save" | "load" if arg.is_empty() => return Some(Err("Please give a file name")),
+            "chat" if arg.is_empty() => return Some(Err("Please give a message")),
+            "save" => Command::Save(arg.to_string()),
+            "load" => Command::Load(arg.to_string()),
+            "flip" => Command::Flip,
+            "resign" => Command::Resign,
+            "draw" => Command::Draw,
+            "chat" => Command::Chat(arg.to_string()),
+            "quit" => Command::Quit,
+            _ => return None,
+        };
+
+        Some(Ok(command))
+    }
+}
+
 impl Agent for Human {
-    fn make_move(&self, board: &mut board::Board, piece: board::Piece) {
+    fn make_move(&mut self, board: &mut board::Board, _piece: board::Piece) -> Action {
         loop {
-            let col = Human::input("Enter the number of the column you want to insert a piece in");
+            let input = Human::input("Enter the number of the column you want to insert a piece in, or `help`");
 
-            match board.insert_piece(col, piece) {
-                Ok(_) => break,
-                Err(e) => println!("{e}"),
+            let command = match input {
+                Input::Column(col) => {
+                    if board.get_moves().contains(&col) {
+                        return Action::Move(col);
+                    }
+                    println!("Column is full");
+                    continue;
+                }
+                Input::Command(command) => command,
+            };
+
+            match command {
+                Command::Hint => match Engine::default().hint(board) {
+                    Some(hint) => println!("{hint}"),
+                    None => println!("The game is over"),
+                },
+                Command::Eval => {
+                    for score in Engine::default().analyze_all(board) {
+                        let exact = if score.exact { " (exact)" } else { "" };
+                        println!("Column {}: {}{exact}", score.col + 1, score.eval);
+                    }
+                }
+                Command::Help => println!("{HELP}"),
+                Command::Undo => return Action::Undo,
+                Command::Redo => return Action::Redo,
+                Command::Save(path) => return Action::Save(path),
+                Command::Load(path) => return Action::Load(path),
+                Command::Flip => return Action::Flip,
+                Command::Resign => return Action::Resign,
+                Command::Draw => return Action::OfferDraw,
+                Command::Chat(text) => return Action::Chat(text),
+                Command::Quit => return Action::Quit,
+            }
+        }
+    }
+
+    fn accept_draw(&mut self, _board: &board::Board) -> bool {
+        println!("Your opponent offers a draw. Do you accept? (y/n)");
+
+        loop {
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).expect("Failed to read input");
+
+            match input.trim() {
+                "y" | "yes" => return true,
+                "n" | "no" => return false,
+                _ => println!("Please answer y or n"),
             }
         }
     }
 }
 
 impl Human {
-    pub fn input(s: &str) -> usize {
+    pub fn input(s: &str) -> Input {
         println!("{s}");
 
         loop {
@@ -28,15 +139,26 @@ impl Human {
                 .read_line(&mut input)
                 .expect("Failed to read input");
 
-            match input.trim().parse::<usize>() {
+            let input = input.trim();
+
+            match Command::parse(input) {
+                Some(Ok(command)) => return Input::Command(command),
+                Some(Err(e)) => {
+                    println!("{e}");
+                    continue;
+                }
+                None => {}
+            }
+
+            match input.parse::<usize>() {
                 Ok(x) => {
                     if (1..=7).contains(&x) {
-                        return x - 1;
+                        return Input::Column(x - 1);
                     } else {
                         println!("Please input a number from 1 to 7");
                     }
                 }
-                Err(_) => println!("Please input a valid number bigger than 0"),
+                Err(_) => println!("Please input a valid number bigger than 0, or `help` for commands"),
             }
         }
     }
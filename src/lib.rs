@@ -1,33 +1,229 @@
+pub mod api;
+pub mod arena;
+pub mod bench;
 pub mod board;
-mod engine;
-mod human;
+pub mod clock;
+pub mod engine;
+pub mod export;
+pub mod external;
+pub mod human;
+pub mod mcts;
+pub mod net;
+pub mod pgn;
+pub mod ponder;
+pub mod protocol;
+pub mod raster;
+pub mod ratings;
+pub mod record;
+pub mod render;
+pub mod replay;
+pub mod rooms;
+pub mod theme;
+pub mod tournament;
+pub mod tui;
+
+use std::path::Path;
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, io, mem};
 
 use board::{Board, Piece};
+use clock::{Clock, TimeControl, TimeLeft};
 use engine::Engine;
+use external::External;
 use human::Human;
+use mcts::Mcts;
+use net::Remote;
+use pgn::AnnotatedGame;
+use ponder::Ponder;
+use record::{GameRecord, GameResult, PlayerRecord};
+use render::Renderer;
 
-enum Player {
+/// Who controls one of the pieces
+pub enum Player {
     Human(Human),
     Engine(Engine),
+    Mcts(Mcts),
+    Remote(Remote),
+    External(External),
 }
 
 impl Agent for Player {
-    fn make_move(&self, board: &mut Board, piece: Piece) {
+    fn make_move(&mut self, board: &mut Board, piece: Piece) -> Action {
         match self {
             Player::Human(h) => h.make_move(board, piece),
             Player::Engine(e) => e.make_move(board, piece),
+            Player::Mcts(m) => m.make_move(board, piece),
+            Player::Remote(r) => r.make_move(board, piece),
+            Player::External(e) => e.make_move(board, piece),
+        }
+    }
+
+    fn set_moves(&mut self, moves: &[usize]) {
+        if let Player::External(e) = self {
+            e.set_moves(moves);
+        }
+    }
+
+    fn set_time(&mut self, time: &TimeLeft) {
+        match self {
+            Player::Engine(e) => e.set_time(time),
+            Player::External(e) => e.set_time(time),
+            _ => {}
+        }
+    }
+
+    fn observe(&mut self, action: &Action) -> io::Result<()> {
+        match self {
+            Player::Remote(r) => r.observe(action),
+            _ => Ok(()),
+        }
+    }
+
+    fn chat(&mut self) -> Vec<String> {
+        match self {
+            Player::Remote(r) => r.chat(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn accept_draw(&mut self, board: &Board) -> bool {
+        match self {
+            Player::Human(h) => h.accept_draw(board),
+            Player::Engine(e) => e.accept_draw(board),
+            Player::Mcts(m) => m.accept_draw(board),
+            Player::Remote(r) => r.accept_draw(board),
+            Player::External(e) => e.accept_draw(board),
+        }
+    }
+
+    fn draw_answered(&mut self, accepted: bool) {
+        if let Player::Remote(r) = self {
+            r.draw_answered(accepted);
         }
     }
 }
 
+impl Player {
+    fn record(&self) -> PlayerRecord {
+        match self {
+            Player::Human(_) => PlayerRecord::Human,
+            Player::Engine(e) if e.weights == engine::EVAL_WEIGHTS => PlayerRecord::Engine { depth: e.depth },
+            Player::Engine(e) => PlayerRecord::Tuned { depth: e.depth, weights: e.weights },
+            Player::Mcts(m) => PlayerRecord::Mcts { playouts: m.playouts },
+            Player::Remote(r) => PlayerRecord::Remote {
+                address: r.peer.clone(),
+            },
+            Player::External(e) => PlayerRecord::External {
+                command: external::join_command(&e.command),
+            },
+        }
+    }
+
+    /// Starts the player described by `record`, giving engine programs
+    /// `movetime` for each move.
+    ///
+    /// This runs the command of an external player, so it is only for
+    /// players given on the command line, never ones read from a file.
+    pub fn launch(record: &PlayerRecord, movetime: Duration) -> io::Result<Player> {
+        match record {
+            PlayerRecord::External { command } => {
+                let command = external::split_command(command).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                External::launch(&command, movetime).map(Player::External)
+            }
+            _ => Ok(Player::from_record(record)),
+        }
+    }
+
+    /// The player for a side of a loaded game. Files can come from anyone,
+    /// so an engine program named in one is never run, and a human plays
+    /// its side instead, like for a remote player whose connection is gone.
+    fn from_record(record: &PlayerRecord) -> Player {
+        match record {
+            PlayerRecord::Engine { depth } => Player::Engine(Engine { depth: *depth, ..Engine::default() }),
+            PlayerRecord::Tuned { depth, weights } => Player::Engine(Engine { depth: *depth, weights: *weights, ..Engine::default() }),
+            PlayerRecord::Mcts { playouts } => Player::Mcts(Mcts { playouts: *playouts }),
+            PlayerRecord::Human | PlayerRecord::Remote { .. } | PlayerRecord::External { .. } => Player::Human(Human),
+        }
+    }
+}
+
+/// What an agent wants to do on its turn
+enum Action {
+    Move(usize),
+    Undo,
+    Redo,
+    Save(String),
+    Load(String),
+    /// Swap which player controls which piece
+    Flip,
+    Resign,
+    /// Offer the other player a draw, keeping the turn if they decline
+    OfferDraw,
+    Chat(String),
+    Quit,
+    /// The player left or could not be reached, for the given reason
+    Disconnect(String),
+}
+
 trait Agent {
-    fn make_move(&self, board: &mut Board, piece: Piece);
+    fn make_move(&mut self, board: &mut Board, piece: Piece) -> Action;
+
+    /// Called before every turn with the moves played so far, for agents
+    /// that need the whole game rather than just the board
+    fn set_moves(&mut self, _moves: &[usize]) {}
+
+    /// Called at the start of every turn in a timed game with what is left
+    /// on the player's clock, so engines can budget their search
+    fn set_time(&mut self, _time: &TimeLeft) {}
+
+    /// Called with every move, resignation, chat message and quit of the
+    /// other player, so remote players can pass them on
+    fn observe(&mut self, _action: &Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Chat the player sent when it was not their turn
+    fn chat(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Whether to agree to a draw the other player offered
+    fn accept_draw(&mut self, _board: &Board) -> bool {
+        false
+    }
+
+    /// Tells the player who offered a draw how the other player answered
+    fn draw_answered(&mut self, _accepted: bool) {}
 }
 
 pub struct Game {
     x: Player,
     o: Player,
     board: Board,
+    moves: Vec<usize>,
+    undone: Vec<usize>,
+    resigned: Option<Piece>,
+    draw_agreed: bool,
+    clock: Option<Clock>,
+    /// The piece whose clock ran out
+    timed_out: Option<Piece>,
+    /// Why the game stopped early, when a player left or was lost
+    disconnected: Option<String>,
+    /// Whether engines think on their human opponent's time
+    pondering: bool,
+    ponder: Option<Ponder>,
+    started: u64,
+    finished: Option<u64>,
+    renderer: Box<dyn Renderer>,
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 impl Default for Game {
@@ -38,50 +234,488 @@ impl Default for Game {
 
 impl Game {
     pub fn new() -> Game {
+        Game::with_players(Player::Human(Human), Player::Engine(Engine::default()))
+    }
+
+    /// A game between the local human and `remote`, who plays `remote_side`
+    pub fn networked(remote: Remote, remote_side: Piece) -> Game {
+        match remote_side {
+            Piece::O => Game::with_players(Player::Human(Human), Player::Remote(remote)),
+            _ => Game::with_players(Player::Remote(remote), Player::Human(Human)),
+        }
+    }
+
+    pub fn with_players(x: Player, o: Player) -> Game {
         Game {
-            x: Player::Human(Human),
-            o: Player::Engine(Engine),
+            x,
+            o,
             board: Board::new(),
+            moves: Vec::new(),
+            undone: Vec::new(),
+            resigned: None,
+            draw_agreed: false,
+            clock: None,
+            timed_out: None,
+            disconnected: None,
+            pondering: false,
+            ponder: None,
+            started: now(),
+            finished: None,
+            renderer: render::detect(),
         }
     }
+
+    /// Gives both sides a clock, which starts with the first turn
+    pub fn set_clock(&mut self, control: TimeControl) {
+        self.clock = Some(Clock::new(control));
+    }
+
+    /// Lets engines search in the background while a human thinks
+    pub fn set_pondering(&mut self, pondering: bool) {
+        self.pondering = pondering;
+    }
+
     pub fn run(&mut self) {
-        let mut turn = 0;
+        let mut status = String::new();
+
         loop {
-            self.board.display();
+            if let Some(chat) = self.pending_chat() {
+                status = if status.is_empty() { chat } else { format!("{status}\n{chat}") };
+            }
+            self.display();
+            if let Some(clock) = &self.clock {
+                println!("{clock}");
+            }
+            if !status.is_empty() {
+                println!("{status}");
+            }
 
-            let piece = if turn == 0 { Piece::O } else { Piece::X };
-            let current = if piece == Piece::O { &self.o } else { &self.x };
+            if let Some(outcome) = self.outcome() {
+                println!("{outcome}");
+                self.finished.get_or_insert_with(now);
+                break;
+            }
 
-            current.make_move(&mut self.board, piece);
+            let piece = self.board.to_move();
+            if let Player::Remote(remote) = self.player(piece) {
+                println!("Waiting for {} to move...", remote.peer);
+            }
+            let action = self.ask(piece);
+            // Text input cannot be interrupted, so the flag is only seen
+            // once the player answers
+            if self.check_flag(piece) {
+                continue;
+            }
 
-            if self.board.is_full() {
-                self.board.display();
-                println!("Board is full, its a draw");
+            match self.apply(action) {
+                Some(message) => status = message,
+                None => break,
+            }
+        }
+    }
+
+    /// Plays the game to the end without showing anything or reading
+    /// input, for games between engines
+    pub fn play_out(&mut self) -> GameResult {
+        while self.result() == GameResult::Ongoing {
+            let piece = self.board.to_move();
+            self.start_clock(piece);
+            let action = match self.player(piece) {
+                // The engine's agent prints its progress, so it is searched directly
+                Player::Engine(engine) => {
+                    let (col, eval) = engine.best_move(&self.board);
+                    self.board.eval = eval;
+                    Action::Move(col)
+                }
+                _ => self.ask(piece),
+            };
+            if self.check_flag(piece) {
+                break;
+            }
+
+            if self.apply(action).is_none() {
                 break;
             }
+        }
+
+        self.finished.get_or_insert_with(now);
+        self.result()
+    }
+
+    /// Describes how the game ended, or `None` while it is still going
+    fn outcome(&self) -> Option<String> {
+        match self.result() {
+            GameResult::Ongoing => None,
+            GameResult::Draw if self.draw_agreed => Some("The players agreed to a draw".to_string()),
+            GameResult::Draw => Some("Board is full, its a draw".to_string()),
+            GameResult::Win(piece) => Some(format!("{piece:?} won!")),
+            // Engine programs resign when they fail, so say why
+            GameResult::Resigned(piece) => match self.player(piece) {
+                Player::External(External { error: Some(e), .. }) => {
+                    Some(format!("{piece:?} forfeits: {e}, {:?} won!", piece.opponent()))
+                }
+                _ => Some(format!("{piece:?} resigned, {:?} won!", piece.opponent())),
+            },
+            GameResult::TimeForfeit(piece) => {
+                Some(format!("{piece:?} ran out of time, {:?} won!", piece.opponent()))
+            }
+        }
+    }
+
+    /// Carries out an action for the piece to move and returns a status
+    /// message for the player, or `None` if the player wants to quit
+    fn apply(&mut self, action: Action) -> Option<String> {
+        let piece = self.board.to_move();
 
-            if let Some(piece) = self.board.check_win() {
-                match piece {
-                    Piece::X => {
-                        self.board.display();
-                        println!("X won!");
-                        break;
+        let status = match action {
+            Action::Undo | Action::Redo | Action::Load(_) | Action::Flip if self.is_networked() => {
+                "That is not possible in a network game".to_string()
+            }
+            // Saved games do not keep the clocks, so a timed game could be
+            // saved and loaded again with all the time in the world
+            Action::Undo | Action::Redo | Action::Save(_) | Action::Load(_) if self.clock.is_some() => {
+                "That is not possible in a timed game".to_string()
+            }
+            Action::Move(col) => match self.play(col) {
+                Ok(_) => {
+                    if let Some(clock) = &mut self.clock {
+                        clock.press(piece);
                     }
-                    Piece::O => {
-                        self.board.display();
-                        println!("O won!");
-                        break;
+                    if let Err(e) = self.player_mut(piece.opponent()).observe(&Action::Move(col)) {
+                        return self.disconnect(e);
                     }
-                    _ => panic!("Invalid winner"),
+                    String::new()
+                }
+                Err(e) => e.to_string(),
+            },
+            Action::Undo => {
+                self.undo();
+                String::new()
+            }
+            Action::Redo => {
+                self.redo();
+                String::new()
+            }
+            Action::Save(path) => match self.save(&path) {
+                Ok(_) => format!("Saved game to {path}"),
+                Err(e) => format!("Failed to save game: {e}"),
+            },
+            Action::Load(path) => match Game::load(&path) {
+                Ok(mut game) => {
+                    // The loaded game keeps how this one is displayed
+                    mem::swap(&mut game.renderer, &mut self.renderer);
+                    *self = game;
+                    format!("Loaded game from {path}")
+                }
+                Err(e) => format!("Failed to load game: {e}"),
+            },
+            Action::Flip => {
+                mem::swap(&mut self.x, &mut self.o);
+                String::new()
+            }
+            Action::Resign => {
+                self.resigned = Some(piece);
+                if let Err(e) = self.player_mut(piece.opponent()).observe(&Action::Resign) {
+                    return self.disconnect(e);
+                }
+                String::new()
+            }
+            Action::OfferDraw => {
+                let (opponent, board) = self.player_and_board(piece.opponent());
+                let accepted = opponent.accept_draw(board);
+                self.settle_draw(piece, accepted)
+            }
+            Action::Chat(text) => {
+                let status = format!("{piece:?}: {text}");
+                if let Err(e) = self.player_mut(piece.opponent()).observe(&Action::Chat(text)) {
+                    return self.disconnect(e);
                 }
+                status
+            }
+            Action::Quit => {
+                // Leaving anyway, so a lost connection does not matter
+                self.player_mut(piece.opponent()).observe(&Action::Quit).ok();
+                return None;
+            }
+            Action::Disconnect(reason) => {
+                self.disconnected = Some(reason);
+                return None;
+            }
+        };
+
+        Some(status)
+    }
+
+    /// Stops the game after the other player could not be told about an
+    /// action
+    fn disconnect(&mut self, e: io::Error) -> Option<String> {
+        self.disconnected = Some(format!("Lost the connection: {e}"));
+        None
+    }
+
+    /// Why the game stopped before it finished, if a player left or the
+    /// connection to them was lost
+    pub fn disconnected(&self) -> Option<&str> {
+        self.disconnected.as_deref()
+    }
+
+    /// Chat either player sent out of turn, one message per line
+    fn pending_chat(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        for piece in [Piece::O, Piece::X] {
+            for text in self.player_mut(piece).chat() {
+                lines.push(format!("{piece:?}: {text}"));
             }
+        }
+
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// Asks the player of `piece` for their next action
+    fn ask(&mut self, piece: Piece) -> Action {
+        self.start_clock(piece);
+        if let Some((col, eval)) = self.pondered(piece) {
+            self.board.eval = eval;
+            return Action::Move(col);
+        }
+        self.start_pondering(piece);
+
+        let player = if piece == Piece::O { &mut self.o } else { &mut self.x };
+        player.set_moves(&self.moves);
+        player.make_move(&mut self.board, piece)
+    }
+
+    /// Starts the clock of `piece`, if the game has clocks, and tells the
+    /// player how much time is left
+    fn start_clock(&mut self, piece: Piece) {
+        if let Some(clock) = &mut self.clock {
+            clock.start(piece);
+            let time = clock.time_left(piece);
+            self.player_mut(piece).set_time(&time);
+        }
+    }
+
+    /// Starts the engine playing against `piece` pondering, if pondering
+    /// is on and `piece` is a human about to think
+    fn start_pondering(&mut self, piece: Piece) {
+        if !self.pondering || !self.is_human(piece) || self.board.is_terminal().is_some() {
+            return;
+        }
+        if self.ponder.as_ref().is_some_and(|ponder| ponder.moves() == self.moves) {
+            return;
+        }
+
+        if let Player::Engine(engine) = self.player(piece.opponent()) {
+            self.ponder = Some(Ponder::start(engine, &self.moves));
+        }
+    }
+
+    /// The move and eval the engine playing `piece` found while pondering,
+    /// if it pondered the move that was just played
+    fn pondered(&mut self, piece: Piece) -> Option<(usize, isize)> {
+        if !matches!(self.player(piece), Player::Engine(_)) {
+            return None;
+        }
+
+        // Whatever happened, the engine is searching for itself now
+        let ponder = self.ponder.take()?;
+        let (&reply, before) = self.moves.split_last()?;
+        if before != ponder.moves() {
+            return None;
+        }
 
-            turn = 1 - turn;
+        ponder.finish(reply)
+    }
+
+    /// Ends the game if `piece` has run out of time
+    fn check_flag(&mut self, piece: Piece) -> bool {
+        let flagged = self.clock.as_ref().is_some_and(|clock| clock.is_flagged(piece));
+        if flagged {
+            self.timed_out = Some(piece);
         }
+        flagged
+    }
+
+    /// Ends the game if the draw `offerer` proposed was accepted, and lets
+    /// the offerer know the answer
+    fn settle_draw(&mut self, offerer: Piece, accepted: bool) -> String {
+        self.player_mut(offerer).draw_answered(accepted);
+        if accepted {
+            self.draw_agreed = true;
+            String::new()
+        } else {
+            format!("{:?} declined the draw", offerer.opponent())
+        }
+    }
+
+    fn play(&mut self, col: usize) -> Result<(), &'static str> {
+        let piece = self.board.to_move();
+        self.board.insert_piece(col, piece)?;
+        self.moves.push(col);
+        self.undone.clear();
+
+        Ok(())
+    }
+
+    fn player(&self, piece: Piece) -> &Player {
+        if piece == Piece::O { &self.o } else { &self.x }
+    }
+
+    fn player_mut(&mut self, piece: Piece) -> &mut Player {
+        self.player_and_board(piece).0
+    }
+
+    fn player_and_board(&mut self, piece: Piece) -> (&mut Player, &mut Board) {
+        let player = if piece == Piece::O { &mut self.o } else { &mut self.x };
+        (player, &mut self.board)
+    }
+
+    fn is_networked(&self) -> bool {
+        matches!(self.x, Player::Remote(_)) || matches!(self.o, Player::Remote(_))
+    }
+
+    fn is_human(&self, piece: Piece) -> bool {
+        matches!(self.player(piece), Player::Human(_))
+    }
+
+    /// Takes back moves until it is a human's turn again
+    fn undo(&mut self) {
+        while let Some(col) = self.moves.pop() {
+            self.undone.push(col);
+            self.rebuild_board();
+            if self.is_human(self.board.to_move()) {
+                break;
+            }
+        }
+    }
+
+    /// Replays undone moves until it is a human's turn again
+    fn redo(&mut self) {
+        while let Some(col) = self.undone.pop() {
+            self.moves.push(col);
+            self.rebuild_board();
+            if self.is_human(self.board.to_move()) {
+                break;
+            }
+        }
+    }
+
+    fn rebuild_board(&mut self) {
+        self.board = Board::from_moves(&self.moves).expect("move history is legal");
+    }
+
+    pub fn result(&self) -> GameResult {
+        if let Some(piece) = self.resigned {
+            return GameResult::Resigned(piece);
+        }
+        if let Some(piece) = self.timed_out {
+            return GameResult::TimeForfeit(piece);
+        }
+        if self.draw_agreed {
+            return GameResult::Draw;
+        }
+
+        match self.board.is_terminal() {
+            None => GameResult::Ongoing,
+            Some(Piece::Empty) => GameResult::Draw,
+            Some(piece) => GameResult::Win(piece),
+        }
+    }
+
+    pub fn record(&self) -> GameRecord {
+        GameRecord {
+            version: record::VERSION,
+            x: self.x.record(),
+            o: self.o.record(),
+            first: Piece::O,
+            moves: self.moves.clone(),
+            result: self.result(),
+            started: self.started,
+            finished: self.finished,
+        }
+    }
+
+    pub fn from_record(record: &GameRecord) -> Result<Game, &'static str> {
+        let resigned = match record.result {
+            GameResult::Resigned(piece) => Some(piece),
+            _ => None,
+        };
+
+        let timed_out = match record.result {
+            GameResult::TimeForfeit(piece) => Some(piece),
+            _ => None,
+        };
+
+        let board = record.board()?;
+        let draw_agreed = record.result == GameResult::Draw && board.is_terminal().is_none();
+
+        Ok(Game {
+            x: Player::from_record(&record.x),
+            o: Player::from_record(&record.o),
+            board,
+            moves: record.moves.clone(),
+            undone: Vec::new(),
+            resigned,
+            draw_agreed,
+            clock: None,
+            timed_out,
+            disconnected: None,
+            pondering: false,
+            ponder: None,
+            started: record.started,
+            finished: record.finished,
+            renderer: render::detect(),
+        })
+    }
+
+    /// Saves the game as JSON if the file name ends in `.json`, as annotated
+    /// notation if it ends in `.pgn`, and in the compact text form otherwise
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let record = self.record();
+
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => record.to_json()? + "\n",
+            Some("pgn") => AnnotatedGame::from_record(&record).to_string(),
+            _ => record.to_string() + "\n",
+        };
+
+        fs::write(path, contents)
+    }
+
+    /// Loads a game saved by [`Game::save`] in either format
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Game> {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let contents = fs::read_to_string(path)?;
+        let contents = contents.trim_start();
+        let record = if contents.starts_with('{') {
+            GameRecord::from_json(contents)?
+        } else if contents.starts_with('[') || contents.starts_with("1.") {
+            contents.parse::<AnnotatedGame>().and_then(|game| game.to_record()).map_err(invalid)?
+        } else {
+            contents.parse().map_err(invalid)?
+        };
+
+        Game::from_record(&record).map_err(invalid)
+    }
+
+    pub fn set_renderer(&mut self, renderer: Box<dyn Renderer>) {
+        self.renderer = renderer;
+    }
+
+    fn display(&self) {
+        if self.renderer.clear_screen() {
+            clearscreen::clear().expect("failed to clear screen");
+        }
+
+        println!("Eval: {}", self.board.eval);
+        println!();
+        println!("{}", self.renderer.render(&self.board));
     }
 
     pub fn run_test(&mut self) {
-        self.board.display();
+        self.display();
         engine::Engine::eval(&self.board, Piece::X);
     }
 }
@@ -1,7 +1,792 @@
-use four_in_a_row::Game;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use four_in_a_row::api;
+use four_in_a_row::arena::{self, MatchOptions};
+use four_in_a_row::bench::{self, BenchOptions, Mode};
+use four_in_a_row::board::{self, Board, Piece};
+use four_in_a_row::clock::TimeControl;
+use four_in_a_row::export::{self, Arrow, ImageOptions, MAX_CELL_SIZE};
+use four_in_a_row::engine::Engine;
+use four_in_a_row::external::{self, External};
+use four_in_a_row::human::Human;
+use four_in_a_row::{Game, Player};
+use four_in_a_row::net::Remote;
+use four_in_a_row::protocol;
+use four_in_a_row::ratings::{self, Ratings};
+use four_in_a_row::pgn::AnnotatedGame;
+use four_in_a_row::record::{self, GameRecord, GameResult, PlayerRecord};
+use four_in_a_row::render::{self, Ansi};
+use four_in_a_row::replay::{self, ReplayOptions};
+use four_in_a_row::rooms::{self, ClientMessage, RoomClient, ServerMessage};
+use four_in_a_row::theme::Theme;
+use four_in_a_row::tournament::{Format, Tournament};
+use four_in_a_row::tui::Tui;
+
+const USAGE: &str = "\
+Usage: four-in-a-row [options] [saved game]
+       four-in-a-row export <moves or saved game> <file.svg|file.png> [export options]
+       four-in-a-row replay <moves or saved game> <file.gif> [replay options]
+       four-in-a-row annotate <moves or saved game> <file.pgn> [--depth <n>]
+       four-in-a-row host <address:port> [--side o|x] [options]
+       four-in-a-row join <address:port> [options]
+       four-in-a-row engine
+       four-in-a-row serve <address:port> [--max-depth <n>] [--max-time <ms>]
+       four-in-a-row match <player> <player> [match options]
+       four-in-a-row tournament <file.json> [tournament options] [<player>...]
+       four-in-a-row ratings <file.json>
+       four-in-a-row bench [bench options] [<positions file>...]
+       four-in-a-row rooms serve <address:port>
+       four-in-a-row rooms client <address:port> create [--engine <depth>]
+       four-in-a-row rooms client <address:port> join|watch <room>
+       four-in-a-row rooms client <address:port> rejoin <room> <token>
+
+Options:
+  --tui              play in the full screen terminal UI
+  --render <style>   board style: ascii, ansi, unicode or compact
+  --theme <theme>    colours: classic, colorblind, high-contrast or mono,
+                     for the ansi style and the TUI
+  --external <cmd>   let an engine program play o instead of the built-in
+                     engine, see engine mode below. Quote a path or an
+                     argument with spaces in it. Saved games never start
+                     engine programs, a human plays their side instead
+  --movetime <ms>    time the engine program gets per move, 1000 by default
+  --clock <time>     play with clocks, in minutes per side, optionally with
+                     an increment like 3+2 or a delay like 3d2 in seconds.
+                     Whoever runs out of time loses, and engines share out
+                     their time instead of using --movetime. Timed games
+                     cannot be undone, saved or loaded
+  --ponder           let the engine think while you do
+  --ratings <file>   update the ratings in a file when the game ends
+  --name <name>      who you are in the ratings, human by default
+
+Network games:
+  host waits for one player to join, and plays o, who moves first, unless
+  --side x is given. In a network game `chat <text>` sends a message and
+  `draw` offers a draw.
+
+Engine mode:
+  engine speaks a UCI-like text protocol on stdin and stdout for GUIs and
+  other programs, see the protocol module for the commands.
+
+Server mode:
+  serve answers POST /analyze, /solve and /move with JSON bodies like
+  {\"moves\": \"4453\", \"depth\": 8, \"movetime\": 500}. Requests may ask for
+  at most --max-depth, 12 by default, and --max-time, 5000 ms by default.
+  /solve searches to the end of the game and takes no depth.
+
+Match options:
+  Players are written like in saved games: engine:<depth>,
+  tuned:<depth>:<w0>,<w1>,<w2>,<w3> for the engine scoring open fours with
+  0 to 3 pieces by those weights (1,10,100,1000 by default), mcts:<playouts>
+  for Monte Carlo tree search, or external:<command>. The first player
+  moves first in every other game.
+  --games <n>        number of games, 10 by default
+  --openings <file>  move lists like 4453 to start games from, one per line.
+                     Engines always play the same game from the same
+                     position, so unless one side is an engine program
+                     a match needs a different opening for every two games
+  --movetime <ms>    time engine programs get per move, 1000 by default
+  --save <dir>       save every game as JSON in a directory
+  --ratings <file>   update the ratings in a file after every game
+
+Tournament options:
+  The tournament is saved to the file after every game. Run the command
+  again with just the file to resume an interrupted tournament, which asks
+  before running any engine programs named in the file. Swiss tournaments
+  can have at most one round fewer than the players, rounded up to even.
+  --cycles <n>       play a round robin n times, once by default
+  --swiss <rounds>   use Swiss pairings for this many rounds
+  --openings <file>, --movetime <ms> and --ratings <file> work like in
+                     matches
+
+Ratings:
+  Every player gets an Elo and a Glicko-2 rating, starting at 1500. ratings
+  shows the players in a ratings file, best first.
+
+Bench options:
+  Without files the built in end, middle and opening sets are run. Files
+  have a move list and its score on each line, in the format of the test
+  sets published for Connect Four solvers.
+  --engine           search to --depth like in a game instead of solving,
+                     only checking the wins and losses found
+  --depth <n>        engine depth, 10 by default
+  --driver <d>       alphabeta or mtdf, mtdf by default
+  --ordering <o>     static, or a list of hash, killers and history
+  --movetime <ms>    give up on a position after this long
+  --verbose          print every position and the score found
+
+Rooms:
+  rooms serve runs a WebSocket server with many game rooms. rooms client
+  creates a room, optionally against the engine searching up to depth 12,
+  joins the free seat of one, watches one or takes a seat back after losing
+  the connection. Type a column to move, `resign` or `quit`. Rooms with a
+  game in progress are closed ten minutes after everyone leaves.
+
+Export options:
+  --numbers          number the pieces in the order they were played
+  --threats          mark cells where either side would complete four
+  --no-last-move     leave out the ring around the last piece
+  --arrow <a1-d4>    draw an arrow between two cells, can be repeated
+  --size <pixels>    size of a cell up to 512, 64 by default
+
+Annotate:
+  annotate writes a game as annotated notation with the engine's eval of
+  every move, marking moves that throw away a forced win as mistakes and
+  moves into a forced loss as blunders. --depth sets the engine depth, 8
+  by default.
+
+Replay options:
+  --numbers          number the pieces in the order they were played
+  --delay <ms>       time each position is shown, 600 by default
+  --drop-frames <n>  frames for each falling disc, 0 turns the animation off
+  --size <pixels>    size of a cell up to 512, 64 by default
+
+Colours are left out when NO_COLOR is set, and the plain ascii style is
+used when the output is not a terminal.";
+
+const SIZE_RANGE: &str = "Size must be a number from 1 to 512";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
 
 fn main() {
-    let mut game = Game::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("export") => export(&args[1..]),
+        Some("replay") => replay(&args[1..]),
+        Some("annotate") => annotate(&args[1..]),
+        Some("host") => host(&args[1..]),
+        Some("join") => join(&args[1..]),
+        Some("engine") => {
+            if let Err(e) = protocol::run(io::stdin().lock(), io::stdout()) {
+                fail(&format!("Engine error: {e}"));
+            }
+        }
+        Some("serve") => serve(&args[1..]),
+        Some("rooms") => rooms(&args[1..]),
+        Some("match") => play_match(&args[1..]),
+        Some("tournament") => tournament(&args[1..]),
+        Some("ratings") => show_ratings(&args[1..]),
+        Some("bench") => bench(&args[1..]),
+        _ => play(&args),
+    }
+}
+
+fn play_match(args: &[String]) {
+    let mut options = MatchOptions::default();
+    let mut players = Vec::new();
+    let mut ratings = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => {
+                let games = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0);
+                options.games = games.unwrap_or_else(|| fail("Games must be a positive number"));
+            }
+            "--openings" => {
+                let path = args.next().unwrap_or_else(|| fail(USAGE));
+                let text = fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("Failed to read {path}: {e}")));
+                options.openings = arena::parse_openings(&text).unwrap_or_else(|e| fail(&format!("Bad opening in {path}: {e}")));
+            }
+            "--movetime" => {
+                let ms = args.next().and_then(|ms| ms.parse().ok());
+                options.movetime = Duration::from_millis(ms.unwrap_or_else(|| fail("Movetime must be a number of milliseconds")));
+            }
+            "--save" => options.save_to = Some(args.next().unwrap_or_else(|| fail(USAGE)).into()),
+            "--ratings" => ratings = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            player => players.push(player.parse::<PlayerRecord>().unwrap_or_else(|e| fail(e))),
+        }
+    }
+
+    let [a, b] = &players[..] else {
+        fail(USAGE);
+    };
+    if !players.iter().all(is_computer) {
+        fail("Only engines can play matches");
+    }
+
+    let result = arena::run_match(a, b, &options, |i, record| {
+        println!("Game {}: {} vs {}, {} {}", i + 1, record.o, record.x, board::format_moves(&record.moves), record.result);
+        if let Some(path) = ratings {
+            rate(path, &record.o, &record.x, record.result, "human");
+        }
+    });
+
+    match result {
+        Ok(result) => println!("\n{result}"),
+        Err(e) => fail(&format!("The match stopped: {e}")),
+    }
+}
+
+fn tournament(args: &[String]) {
+    let mut format = Format::RoundRobin { cycles: 1 };
+    let mut openings = Vec::new();
+    let mut movetime = Duration::from_secs(1);
+    let mut players = Vec::new();
+    let mut path = None;
+    let mut ratings = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = || args.next().and_then(|n| n.parse::<u64>().ok()).filter(|&n| n > 0);
+        match arg.as_str() {
+            "--cycles" => {
+                let cycles = number().unwrap_or_else(|| fail("Cycles must be a positive number"));
+                format = Format::RoundRobin { cycles: cycles as usize };
+            }
+            "--swiss" => {
+                let rounds = number().unwrap_or_else(|| fail("Rounds must be a positive number"));
+                format = Format::Swiss { rounds: rounds as usize };
+            }
+            "--movetime" => {
+                movetime = Duration::from_millis(number().unwrap_or_else(|| fail("Movetime must be a number of milliseconds")));
+            }
+            "--openings" => {
+                let file = args.next().unwrap_or_else(|| fail(USAGE));
+                let text = fs::read_to_string(file).unwrap_or_else(|e| fail(&format!("Failed to read {file}: {e}")));
+                openings = arena::parse_openings(&text).unwrap_or_else(|e| fail(&format!("Bad opening in {file}: {e}")));
+            }
+            "--ratings" => ratings = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            player => players.push(player.parse::<PlayerRecord>().unwrap_or_else(|e| fail(e))),
+        }
+    }
+
+    let Some(path) = path else {
+        fail(USAGE);
+    };
+    if !players.iter().all(is_computer) {
+        fail("Only engines can play in tournaments");
+    }
+
+    let mut tournament = if players.is_empty() {
+        let mut tournament = Tournament::load(path).unwrap_or_else(|e| fail(&format!("Failed to resume {path}: {e}")));
+        let programs = tournament.programs();
+        if !programs.is_empty() {
+            println!("{path} runs these engine programs:");
+            for program in programs {
+                println!("  {program}");
+            }
+            if !confirm("Run them?") {
+                fail("The tournament was not resumed");
+            }
+            tournament.allow_programs();
+        }
+        tournament
+    } else {
+        Tournament::new(format, players, openings, movetime).unwrap_or_else(|e| fail(e))
+    };
+
+    let result = tournament.run(Some(path.as_ref()), |tournament, game| {
+        let name = |player: usize| tournament.players[player].to_string();
+        let result = game.result.map(|result| result.to_string()).unwrap_or_default();
+        println!("Round {}: {} vs {}, {result}", game.round, name(game.o), name(game.x));
+        if let (Some(path), Some(result)) = (ratings, game.result) {
+            rate(path, &tournament.players[game.o], &tournament.players[game.x], result, "human");
+        }
+    });
+    if let Err(e) = result {
+        fail(&format!("The tournament stopped: {e}"));
+    }
+
+    println!();
+    print!("{}", tournament.crosstable());
+}
+
+/// Whether `player` can play matches and tournaments on its own
+fn is_computer(player: &PlayerRecord) -> bool {
+    !matches!(player, PlayerRecord::Human | PlayerRecord::Remote { .. })
+}
+
+/// Asks a yes or no question on the terminal, taking anything but yes as no
+fn confirm(question: &str) -> bool {
+    print!("{question} [y/N] ");
+    io::stdout().flush().ok();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+fn show_ratings(args: &[String]) {
+    let [path] = args else {
+        fail(USAGE);
+    };
+
+    let ratings = Ratings::load(path).unwrap_or_else(|e| fail(&format!("Failed to read {path}: {e}")));
+    if ratings.players.is_empty() {
+        println!("No rated games yet");
+    } else {
+        print!("{}", ratings.leaderboard());
+    }
+}
+
+fn bench(args: &[String]) {
+    let mut options = BenchOptions::default();
+    let mut files = Vec::new();
+    let mut verbose = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().map(String::as_str).unwrap_or_else(|| fail(USAGE));
+        match arg.as_str() {
+            "--engine" => options.mode = Mode::Engine,
+            "--depth" => {
+                let depth = value().parse().ok().filter(|&depth| depth > 0);
+                options.engine.depth = depth.unwrap_or_else(|| fail("Depth must be a positive number"));
+            }
+            "--driver" => options.engine.driver = value().parse().unwrap_or_else(|e| fail(e)),
+            "--ordering" => options.engine.ordering = value().parse().unwrap_or_else(|e| fail(e)),
+            "--movetime" => {
+                let ms = value().parse().unwrap_or_else(|_| fail("Movetime must be a number of milliseconds"));
+                options.movetime = Some(Duration::from_millis(ms));
+            }
+            "--verbose" => verbose = true,
+            file => files.push(file),
+        }
+    }
+
+    let suites: Vec<(String, String)> = if files.is_empty() {
+        bench::SUITES.iter().map(|(name, text)| (name.to_string(), text.to_string())).collect()
+    } else {
+        files
+            .iter()
+            .map(|&file| {
+                let text = fs::read_to_string(file).unwrap_or_else(|e| fail(&format!("Failed to read {file}: {e}")));
+                (file.to_string(), text)
+            })
+            .collect()
+    };
+
+    for (name, text) in suites {
+        let positions = bench::parse_positions(&text).unwrap_or_else(|e| fail(&format!("Bad position in {name}: {e}")));
+        let report = bench::run(&name, &positions, &options, |position, found| {
+            if verbose {
+                let found = found.map_or("unsolved".to_string(), |score| score.to_string());
+                println!("{} {}: {found}", board::format_moves(&position.moves), position.score);
+            }
+        });
+        println!("{report}");
+    }
+}
+
+/// Records a finished game in the ratings file at `path`. A game that
+/// cannot be rated is not worth stopping for, so failures are only printed.
+/// Humans are rated as `human`.
+fn rate(path: &str, o: &PlayerRecord, x: &PlayerRecord, result: GameResult, human: &str) {
+    let (o, x) = (ratings::player_name(o, human), ratings::player_name(x, human));
+    if let Err(e) = Ratings::record_in(path, &o, &x, result) {
+        eprintln!("Failed to update the ratings in {path}: {e}");
+    }
+}
+
+fn rooms(args: &[String]) {
+    let words: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let (address, message) = match words[..] {
+        ["serve", address] => {
+            let listener = TcpListener::bind(address).unwrap_or_else(|e| fail(&format!("Failed to listen on {address}: {e}")));
+            println!("Serving rooms on {address}");
+            rooms::serve(listener);
+            return;
+        }
+        ["client", address, "create"] => (address, ClientMessage::Create { engine: None }),
+        ["client", address, "create", "--engine", depth] => {
+            let depth = depth.parse().unwrap_or_else(|_| fail("Engine depth must be a number"));
+            (address, ClientMessage::Create { engine: Some(depth) })
+        }
+        ["client", address, "join", room] => (address, ClientMessage::Join { room: room.to_string() }),
+        ["client", address, "watch", room] => (address, ClientMessage::Watch { room: room.to_string() }),
+        ["client", address, "rejoin", room, token] => (
+            address,
+            ClientMessage::Rejoin {
+                room: room.to_string(),
+                token: token.to_string(),
+            },
+        ),
+        _ => fail(USAGE),
+    };
+
+    if let Err(e) = room_client(address, message) {
+        fail(&format!("Connection to {address} failed: {e}"));
+    }
+}
+
+/// Shows the room after every change and sends what is typed
+fn room_client(address: &str, first: ClientMessage) -> tungstenite::Result<()> {
+    let mut client = RoomClient::connect(address)?;
+    client.send(&first)?;
+
+    // Reading stdin on its own thread keeps the board updating while the
+    // player thinks
+    let (sender, input) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        while let Ok(line) = input.try_recv() {
+            match line.trim() {
+                "quit" => return Ok(()),
+                "resign" => client.send(&ClientMessage::Resign)?,
+                column => match column.parse() {
+                    Ok(column) => client.send(&ClientMessage::Move { column })?,
+                    Err(_) => println!("Type a column from 1 to 7, `resign` or `quit`"),
+                },
+            }
+        }
+
+        match client.poll()? {
+            Some(ServerMessage::Joined { room, seat: Some(seat), token }) => {
+                println!("Playing {seat:?} in room {room}, rejoin with token {}", token.unwrap_or_default());
+            }
+            Some(ServerMessage::Joined { room, .. }) => println!("Watching room {room}"),
+            Some(ServerMessage::State(state)) => {
+                let moves = board::parse_moves(&state.moves).unwrap_or_default();
+                let board = Board::from_moves(&moves).unwrap_or_default();
+                println!("{board}");
+                println!(
+                    "O: {:?}, X: {:?}, spectators: {}, result: {}, to move: {:?}",
+                    state.o, state.x, state.spectators, state.result, state.to_move
+                );
+            }
+            Some(ServerMessage::Error { message }) => println!("{message}"),
+            None => {}
+        }
+    }
+}
+
+fn serve(args: &[String]) {
+    let mut limits = api::Limits::default();
+    let mut address = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = || args.next().and_then(|n| n.parse::<u64>().ok()).filter(|&n| n > 0);
+        match arg.as_str() {
+            "--max-depth" => {
+                let depth = number().and_then(|n| u32::try_from(n).ok());
+                limits.max_depth = depth.unwrap_or_else(|| fail("Max depth must be a positive number"));
+            }
+            "--max-time" => {
+                let ms = number().unwrap_or_else(|| fail("Max time must be a number of milliseconds"));
+                limits.max_time = Duration::from_millis(ms);
+            }
+            _ => address = Some(arg.as_str()),
+        }
+    }
+
+    let Some(address) = address else {
+        fail(USAGE);
+    };
+
+    println!("Serving on {address}");
+    if let Err(e) = api::serve(address, limits) {
+        fail(&format!("Failed to serve on {address}: {e}"));
+    }
+}
+
+/// Reads a move list like 4453, or the moves of a saved game
+fn load_moves(source: &str) -> Vec<usize> {
+    load_record(source).moves
+}
+
+/// Reads a saved game, or makes a record of a game between two humans
+/// from a move list
+fn load_record(source: &str) -> GameRecord {
+    let Ok(moves) = board::parse_moves(source) else {
+        return match Game::load(source) {
+            Ok(game) => game.record(),
+            Err(e) => fail(&format!("`{source}` is neither a move list nor a saved game: {e}")),
+        };
+    };
+
+    let board = Board::from_moves(&moves).unwrap_or_else(|e| fail(e));
+    let result = match board.is_terminal() {
+        None => GameResult::Ongoing,
+        Some(Piece::Empty) => GameResult::Draw,
+        Some(piece) => GameResult::Win(piece),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+
+    GameRecord {
+        version: record::VERSION,
+        x: PlayerRecord::Human,
+        o: PlayerRecord::Human,
+        first: Piece::O,
+        moves,
+        result,
+        started: now,
+        finished: None,
+    }
+}
+
+fn annotate(args: &[String]) {
+    let mut depth = 8;
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => {
+                let n = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0);
+                depth = n.unwrap_or_else(|| fail("Depth must be a positive number"));
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let [source, path] = positional[..] else {
+        fail(USAGE);
+    };
+
+    let mut game = AnnotatedGame::from_record(&load_record(source));
+    game.annotate(&Engine { depth, ..Engine::default() });
+
+    if let Err(e) = fs::write(path, game.to_string()) {
+        fail(&format!("Failed to write {path}: {e}"));
+    }
+}
+
+fn export(args: &[String]) {
+    let mut options = ImageOptions::default();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--numbers" => options.moves = Some(Vec::new()),
+            "--threats" => options.threats = true,
+            "--no-last-move" => options.last_move = false,
+            "--arrow" => {
+                let arrow = args.next().map(String::as_str).unwrap_or_default();
+                options.arrows.push(Arrow::parse(arrow).unwrap_or_else(|e| fail(e)));
+            }
+            "--size" => {
+                let size = args.next().and_then(|size| size.parse().ok());
+                options.cell_size = size.filter(|size| (1..=MAX_CELL_SIZE).contains(size)).unwrap_or_else(|| fail(SIZE_RANGE));
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let [source, path] = positional[..] else {
+        fail(USAGE);
+    };
+
+    let moves = load_moves(source);
+    let board = Board::from_moves(&moves).unwrap_or_else(|e| fail(e));
+    if options.moves.is_some() {
+        options.moves = Some(moves);
+    }
+
+    if let Err(e) = export::save(&board, &options, path) {
+        fail(&format!("Failed to write {path}: {e}"));
+    }
+}
+
+fn replay(args: &[String]) {
+    let mut options = ReplayOptions::default();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = || args.next().and_then(|n| n.parse::<u64>().ok());
+        match arg.as_str() {
+            "--numbers" => options.image.moves = Some(Vec::new()),
+            "--delay" => {
+                let delay = number().unwrap_or_else(|| fail("Delay must be a number of milliseconds"));
+                options.frame_delay = Duration::from_millis(delay);
+            }
+            "--drop-frames" => {
+                let frames = number().and_then(|n| u32::try_from(n).ok());
+                options.drop_frames = frames.unwrap_or_else(|| fail("Drop frames must be a number"));
+            }
+            "--size" => {
+                let size = number().and_then(|n| u32::try_from(n).ok()).filter(|size| (1..=MAX_CELL_SIZE).contains(size));
+                options.image.cell_size = size.unwrap_or_else(|| fail(SIZE_RANGE));
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let [source, path] = positional[..] else {
+        fail(USAGE);
+    };
+
+    if let Err(e) = replay::save(&load_moves(source), &options, path) {
+        fail(&format!("Failed to write {path}: {e}"));
+    }
+}
+
+/// How the board is shown and where the result is rated, shared by local
+/// and network games
+#[derive(Default)]
+struct DisplayOptions {
+    tui: bool,
+    renderer: Option<String>,
+    theme: Option<Theme>,
+    ratings: Option<String>,
+    name: Option<String>,
+}
+
+/// Reads the display options, returning the arguments left over
+fn display_options(args: &[String]) -> (DisplayOptions, Vec<String>) {
+    let mut options = DisplayOptions::default();
+    let mut rest = Vec::new();
+
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tui" => options.tui = true,
+            "--render" => {
+                let name = args.next().unwrap_or_default();
+                if render::by_name(&name).is_none() {
+                    fail(&format!("Unknown board style `{name}`\n\n{USAGE}"));
+                }
+                options.renderer = Some(name);
+            }
+            "--theme" => {
+                let name = args.next().unwrap_or_default();
+                options.theme = Some(Theme::by_name(&name).unwrap_or_else(|| fail(&format!("Unknown theme `{name}`\n\n{USAGE}"))));
+            }
+            "--ratings" => options.ratings = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "--name" => options.name = Some(args.next().unwrap_or_else(|| fail(USAGE))),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => rest.push(arg),
+        }
+    }
+
+    // Only the ansi style and the TUI have colours to theme
+    if options.theme.is_some() && !options.tui && options.renderer.as_deref().is_some_and(|name| name != "ansi") {
+        fail("--theme only works with the ansi style or --tui");
+    }
+
+    (options, rest)
+}
+
+fn play(args: &[String]) {
+    let (options, rest) = display_options(args);
+
+    let mut external = None;
+    let mut movetime = Duration::from_secs(1);
+    let mut clock = None;
+    let mut ponder = false;
+    let mut path = None;
+
+    let mut rest = rest.into_iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--external" => external = Some(rest.next().unwrap_or_else(|| fail(USAGE))),
+            "--movetime" => {
+                let ms = rest.next().and_then(|ms| ms.parse().ok());
+                movetime = Duration::from_millis(ms.unwrap_or_else(|| fail("Movetime must be a number of milliseconds")));
+            }
+            "--clock" => {
+                let control = rest.next().unwrap_or_else(|| fail(USAGE));
+                clock = Some(control.parse::<TimeControl>().unwrap_or_else(|e| fail(e)));
+            }
+            "--ponder" => ponder = true,
+            _ => path = Some(arg),
+        }
+    }
+
+    // A saved game can be resumed by passing its file
+    let mut game = match (path, external) {
+        (Some(_), Some(_)) => fail("A saved game already says who plays"),
+        (Some(path), None) => Game::load(&path).unwrap_or_else(|e| fail(&format!("Failed to load {path}: {e}"))),
+        (None, Some(command)) => {
+            let command = external::split_command(&command).unwrap_or_else(|e| fail(e));
+            let engine = External::launch(&command, movetime)
+                .unwrap_or_else(|e| fail(&format!("Failed to start {}: {e}", external::join_command(&command))));
+            Game::with_players(Player::Human(Human), Player::External(engine))
+        }
+        (None, None) => Game::new(),
+    };
+    if let Some(control) = clock {
+        game.set_clock(control);
+    }
+    game.set_pondering(ponder);
+
+    start(game, &options);
+}
+
+fn host(args: &[String]) {
+    let (options, rest) = display_options(args);
+
+    let (address, side) = match &rest[..] {
+        [address] => (address, Piece::O),
+        [address, flag, side] if flag == "--side" => match side.as_str() {
+            "o" => (address, Piece::O),
+            "x" => (address, Piece::X),
+            _ => fail("Side must be o or x"),
+        },
+        _ => fail(USAGE),
+    };
+
+    let listener = TcpListener::bind(address).unwrap_or_else(|e| fail(&format!("Failed to listen on {address}: {e}")));
+    println!("Waiting for someone to join on {address}...");
+    let remote = Remote::accept(&listener, side.opponent()).unwrap_or_else(|e| fail(&format!("Failed to start the game: {e}")));
+    println!("{} joined, you play {side:?}", remote.peer);
+
+    start(Game::networked(remote, side.opponent()), &options);
+}
+
+fn join(args: &[String]) {
+    let (options, rest) = display_options(args);
+
+    let [address] = &rest[..] else {
+        fail(USAGE);
+    };
+
+    let (remote, host_side) = Remote::connect(address).unwrap_or_else(|e| fail(&format!("Failed to join {address}: {e}")));
+    println!("Joined {address}, you play {:?}", host_side.opponent());
+
+    start(Game::networked(remote, host_side), &options);
+}
+
+fn start(mut game: Game, options: &DisplayOptions) {
+    match (options.renderer.as_deref(), options.theme) {
+        (None | Some("ansi"), Some(theme)) => game.set_renderer(Box::new(Ansi { theme })),
+        // With --tui the theme is only used by the TUI itself
+        (Some(name), _) => game.set_renderer(render::by_name(name).expect("style was checked")),
+        (None, None) => {}
+    }
+
+    if options.tui {
+        let mut tui = Tui::new(&mut game);
+        if let Some(theme) = options.theme {
+            tui.set_theme(theme);
+        }
+        if let Err(e) = tui.run() {
+            fail(&format!("Terminal error: {e}"));
+        }
+    } else {
+        game.run();
+    }
+    if let Some(reason) = game.disconnected() {
+        eprintln!("{reason}");
+    }
 
-    game.run();
+    if let Some(path) = &options.ratings {
+        let record = game.record();
+        rate(path, &record.o, &record.x, record.result, options.name.as_deref().unwrap_or("human"));
+    }
 }
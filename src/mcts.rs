@@ -0,0 +1,167 @@
+//! Monte Carlo tree search, a player that needs no eval. It plays random
+//! games from the position and grows a tree towards the moves that won
+//! most of them, so it plays differently from the alpha-beta engine and
+//! makes a useful opponent for it in matches and tournaments.
+//!
+//! The random moves come from a fixed seed, so like the engine it always
+//! plays the same move in the same position.
+
+use crate::board::{Board, Piece};
+use crate::{Action, Agent};
+
+/// How much the search favours moves it has tried less, the usual value
+/// for results between 0 and 1
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[derive(Clone, Debug)]
+pub struct Mcts {
+    /// Random games played for every move
+    pub playouts: u32,
+}
+
+struct Node {
+    /// The piece that played the move leading here
+    mover: Piece,
+    untried: Vec<usize>,
+    /// Columns and the nodes they lead to
+    children: Vec<(usize, usize)>,
+    visits: u32,
+    /// Points `mover` scored in the games through this node, a half for a
+    /// draw
+    score: f64,
+}
+
+impl Node {
+    fn new(board: &Board) -> Node {
+        let untried = if board.is_terminal().is_some() { Vec::new() } else { board.get_moves() };
+        Node {
+            mover: board.to_move().opponent(),
+            untried,
+            children: Vec::new(),
+            visits: 0,
+            score: 0.0,
+        }
+    }
+
+    /// The upper confidence bound on the node's score, seen from its parent
+    fn bound(&self, parent_visits: u32) -> f64 {
+        let visits = f64::from(self.visits);
+        self.score / visits + EXPLORATION * (f64::from(parent_visits).ln() / visits).sqrt()
+    }
+}
+
+/// A xorshift generator, which is plenty for picking random moves
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+impl Agent for Mcts {
+    fn make_move(&mut self, board: &mut Board, _piece: Piece) -> Action {
+        Action::Move(self.best_move(board))
+    }
+}
+
+impl Mcts {
+    /// The column played most in the search from `board`. A winning move is
+    /// played straight away.
+    ///
+    /// # Panics
+    ///
+    /// If the game is already over.
+    pub fn best_move(&self, board: &Board) -> usize {
+        assert!(board.is_terminal().is_none(), "the game is already over");
+        if let Some(&col) = board.winning_moves(board.to_move()).first() {
+            return col;
+        }
+
+        let mut random = Random((SEED ^ board.key()).max(1));
+        let mut nodes = vec![Node::new(board)];
+
+        for _ in 0..self.playouts.max(1) {
+            let mut board = board.clone();
+            let mut path = vec![0];
+            let mut node = 0;
+
+            // Down the tree through the best bounds, while every move of a
+            // node has been tried
+            while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+                let visits = nodes[node].visits;
+                let &(col, child) = nodes[node]
+                    .children
+                    .iter()
+                    .max_by(|a, b| nodes[a.1].bound(visits).total_cmp(&nodes[b.1].bound(visits)))
+                    .expect("node has children");
+                board.insert_piece(col, board.to_move()).expect("tree moves are legal");
+                node = child;
+                path.push(node);
+            }
+
+            // One new node for a move not tried yet
+            if !nodes[node].untried.is_empty() {
+                let i = random.below(nodes[node].untried.len());
+                let col = nodes[node].untried.swap_remove(i);
+                board.insert_piece(col, board.to_move()).expect("untried moves are legal");
+                nodes.push(Node::new(&board));
+                let child = nodes.len() - 1;
+                nodes[node].children.push((col, child));
+                path.push(child);
+            }
+
+            let winner = Mcts::play_out(&mut board, &mut random);
+            for &node in &path {
+                let node = &mut nodes[node];
+                node.visits += 1;
+                node.score += match winner {
+                    Piece::Empty => 0.5,
+                    winner if winner == node.mover => 1.0,
+                    _ => 0.0,
+                };
+            }
+        }
+
+        let (col, _) = nodes[0]
+            .children
+            .iter()
+            .max_by_key(|&&(_, child)| nodes[child].visits)
+            .expect("the root was expanded");
+        *col
+    }
+
+    /// Plays random moves to the end of the game, returning the winner or
+    /// `Piece::Empty` for a draw
+    fn play_out(board: &mut Board, random: &mut Random) -> Piece {
+        loop {
+            if let Some(result) = board.is_terminal() {
+                return result;
+            }
+
+            let moves = board.get_moves();
+            let col = moves[random.below(moves.len())];
+            board.insert_piece(col, board.to_move()).expect("moves are legal");
+        }
+    }
+}
+
+#[cfg(test)]
+mod mcts_tests {
+    use super::*;
+
+    #[test]
+    fn wins_and_blocks() {
+        let mcts = Mcts { playouts: 500 };
+
+        // O can finish the bottom row in column 4
+        assert_eq!(mcts.best_move(&Board::from_moves(&[0, 0, 1, 1, 2, 2]).unwrap()), 3);
+        // X has to stop the same row
+        assert_eq!(mcts.best_move(&Board::from_moves(&[0, 0, 1, 1, 2]).unwrap()), 3);
+    }
+}
@@ -0,0 +1,341 @@
+//! Playing against someone on another machine over TCP.
+//!
+//! The protocol is one message per line:
+//!
+//! ```text
+//! HELLO 1        both sides, first thing after connecting
+//! SIDE x         host only, the piece the joining player plays
+//! MOVE 4         a column from 1 to 7
+//! RESIGN
+//! DRAW OFFER     answered with DRAW ACCEPT or DRAW DECLINE
+//! CHAT hello there
+//! QUIT
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::board::{Board, Piece};
+use crate::{Action, Agent};
+
+/// Version of the line protocol, sent in the handshake
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Hello(u32),
+    Side(Piece),
+    Move(usize),
+    Resign,
+    DrawOffer,
+    DrawAccept,
+    DrawDecline,
+    Chat(String),
+    Quit,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Hello(version) => write!(f, "HELLO {version}"),
+            Message::Side(Piece::X) => write!(f, "SIDE x"),
+            Message::Side(_) => write!(f, "SIDE o"),
+            Message::Move(col) => write!(f, "MOVE {}", col + 1),
+            Message::Resign => write!(f, "RESIGN"),
+            Message::DrawOffer => write!(f, "DRAW OFFER"),
+            Message::DrawAccept => write!(f, "DRAW ACCEPT"),
+            Message::DrawDecline => write!(f, "DRAW DECLINE"),
+            // Chat has to stay on one line
+            Message::Chat(text) => write!(f, "CHAT {}", text.replace(['\r', '\n'], " ")),
+            Message::Quit => write!(f, "QUIT"),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_end_matches(['\r', '\n']);
+        let (command, arg) = s.split_once(' ').unwrap_or((s, ""));
+
+        match (command, arg) {
+            ("HELLO", version) => Ok(Message::Hello(version.parse().map_err(|_| "Bad protocol version")?)),
+            ("SIDE", "x") => Ok(Message::Side(Piece::X)),
+            ("SIDE", "o") => Ok(Message::Side(Piece::O)),
+            ("MOVE", col) => match col.parse::<usize>() {
+                Ok(col @ 1..=7) => Ok(Message::Move(col - 1)),
+                _ => Err("Moves must be a column from 1 to 7"),
+            },
+            ("RESIGN", "") => Ok(Message::Resign),
+            ("DRAW", "OFFER") => Ok(Message::DrawOffer),
+            ("DRAW", "ACCEPT") => Ok(Message::DrawAccept),
+            ("DRAW", "DECLINE") => Ok(Message::DrawDecline),
+            ("CHAT", text) => Ok(Message::Chat(text.to_string())),
+            ("QUIT", "") => Ok(Message::Quit),
+            _ => Err("Unknown message"),
+        }
+    }
+}
+
+/// The player at the other end of a connection. Their moves arrive over the
+/// network and the local player's actions are sent back.
+///
+/// Messages are read on a thread of their own, so chat sent on the local
+/// player's turn is not stuck in the connection until the remote player's.
+pub struct Remote {
+    incoming: Receiver<io::Result<Message>>,
+    /// Messages read early while looking for chat, in the order they came
+    queued: VecDeque<io::Result<Message>>,
+    /// Chat that arrived outside the remote player's turn
+    chat: Vec<String>,
+    writer: TcpStream,
+    pub peer: String,
+}
+
+/// Passes on messages until the connection closes or sends something that
+/// is not a message
+fn read_messages(mut reader: BufReader<TcpStream>, sender: Sender<io::Result<Message>>) {
+    loop {
+        let mut line = String::new();
+        let message = match reader.read_line(&mut line) {
+            Ok(0) => Ok(Message::Quit),
+            Ok(_) => line.parse().map_err(protocol_error),
+            Err(e) => Err(e),
+        };
+
+        let last = !matches!(message, Ok(ref message) if *message != Message::Quit);
+        if sender.send(message).is_err() || last {
+            return;
+        }
+    }
+}
+
+impl Remote {
+    fn new(stream: TcpStream) -> io::Result<Remote> {
+        let peer = stream.peer_addr()?.to_string();
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || read_messages(reader, sender));
+
+        Ok(Remote {
+            incoming,
+            queued: VecDeque::new(),
+            chat: Vec::new(),
+            writer: stream,
+            peer,
+        })
+    }
+
+    /// Waits for one player to join on `listener` and tells them to play
+    /// `remote_side`
+    pub fn accept(listener: &TcpListener, remote_side: Piece) -> io::Result<Remote> {
+        let (stream, _) = listener.accept()?;
+        let mut remote = Remote::new(stream)?;
+
+        remote.send(&Message::Hello(PROTOCOL_VERSION))?;
+        remote.expect_hello()?;
+        remote.send(&Message::Side(remote_side))?;
+
+        Ok(remote)
+    }
+
+    /// Joins a hosted game, returning the connection and the piece the host
+    /// plays
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<(Remote, Piece)> {
+        let mut remote = Remote::new(TcpStream::connect(addr)?)?;
+
+        remote.send(&Message::Hello(PROTOCOL_VERSION))?;
+        remote.expect_hello()?;
+
+        match remote.receive()? {
+            Message::Side(local) => Ok((remote, local.opponent())),
+            _ => Err(protocol_error("Expected the host to pick a side")),
+        }
+    }
+
+    fn expect_hello(&mut self) -> io::Result<()> {
+        match self.receive()? {
+            Message::Hello(PROTOCOL_VERSION) => Ok(()),
+            Message::Hello(_) => Err(protocol_error("The other player runs a different protocol version")),
+            _ => Err(protocol_error("Expected HELLO")),
+        }
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        writeln!(self.writer, "{message}")?;
+        self.writer.flush()
+    }
+
+    /// Waits for the next message, treating a closed connection as `QUIT`
+    pub fn receive(&mut self) -> io::Result<Message> {
+        match self.queued.pop_front() {
+            Some(message) => message,
+            // The reader only stops after passing on a quit or an error
+            None => self.incoming.recv().unwrap_or(Ok(Message::Quit)),
+        }
+    }
+
+    /// Chat that arrived since it was last taken, without waiting for more
+    pub fn take_chat(&mut self) -> Vec<String> {
+        while let Ok(message) = self.incoming.try_recv() {
+            match message {
+                Ok(Message::Chat(text)) => self.chat.push(text),
+                message => self.queued.push_back(message),
+            }
+        }
+
+        mem::take(&mut self.chat)
+    }
+
+    fn next_action(&mut self, board: &Board) -> io::Result<Action> {
+        match self.receive()? {
+            Message::Move(col) if board.get_moves().contains(&col) => Ok(Action::Move(col)),
+            Message::Move(_) => {
+                self.send(&Message::Quit).ok();
+                Err(protocol_error("The other player sent an illegal move"))
+            }
+            Message::Resign => Ok(Action::Resign),
+            Message::DrawOffer => Ok(Action::OfferDraw),
+            Message::Chat(text) => Ok(Action::Chat(text)),
+            Message::Quit => Ok(Action::Disconnect("The other player left the game".to_string())),
+            _ => Err(protocol_error("Unexpected message")),
+        }
+    }
+}
+
+fn protocol_error(e: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        // Wakes the reader thread up so it can finish
+        self.writer.shutdown(Shutdown::Both).ok();
+    }
+}
+
+impl Agent for Remote {
+    fn make_move(&mut self, board: &mut Board, _piece: Piece) -> Action {
+        self.next_action(board)
+            .unwrap_or_else(|e| Action::Disconnect(format!("Lost the connection: {e}")))
+    }
+
+    fn observe(&mut self, action: &Action) -> io::Result<()> {
+        let message = match action {
+            Action::Move(col) => Message::Move(*col),
+            Action::Resign => Message::Resign,
+            Action::Chat(text) => Message::Chat(text.clone()),
+            Action::Quit => Message::Quit,
+            _ => return Ok(()),
+        };
+
+        self.send(&message)
+    }
+
+    fn chat(&mut self) -> Vec<String> {
+        self.take_chat()
+    }
+
+    fn accept_draw(&mut self, _board: &Board) -> bool {
+        if self.send(&Message::DrawOffer).is_err() {
+            return false;
+        }
+
+        // Chat sent while the other player thinks it over is shown later
+        loop {
+            match self.receive() {
+                Ok(Message::Chat(text)) => self.chat.push(text),
+                Ok(Message::DrawAccept) => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    fn draw_answered(&mut self, accepted: bool) {
+        let answer = if accepted { Message::DrawAccept } else { Message::DrawDecline };
+        self.send(&answer).ok();
+    }
+}
+
+#[cfg(test)]
+mod net_tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            Message::Hello(1),
+            Message::Side(Piece::X),
+            Message::Move(3),
+            Message::Resign,
+            Message::DrawOffer,
+            Message::DrawDecline,
+            Message::Chat("good luck".to_string()),
+            Message::Quit,
+        ];
+
+        for message in messages {
+            assert_eq!(message.to_string().parse(), Ok(message));
+        }
+        assert!("MOVE 8".parse::<Message>().is_err());
+    }
+
+    #[test]
+    fn handshake_and_moves_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let joiner = thread::spawn(move || {
+            let (mut host, host_side) = Remote::connect(addr).unwrap();
+            assert_eq!(host_side, Piece::O);
+
+            let mut board = Board::new();
+            let action = host.make_move(&mut board, Piece::O);
+            assert!(matches!(action, Action::Move(3)));
+
+            // A move into a full column is refused
+            let board = Board::from_moves(&[0, 0, 0, 0, 0, 0]).unwrap();
+            assert!(host.next_action(&board).is_err());
+        });
+
+        let mut joined = Remote::accept(&listener, Piece::X).unwrap();
+        joined.observe(&Action::Move(3)).unwrap();
+        joined.observe(&Action::Move(0)).unwrap();
+
+        joiner.join().unwrap();
+    }
+
+    #[test]
+    fn chat_arrives_on_either_turn() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let joiner = thread::spawn(move || {
+            let (mut host, _) = Remote::connect(addr).unwrap();
+            host.send(&Message::Chat("good luck".to_string())).unwrap();
+            host.send(&Message::Move(3)).unwrap();
+            host.receive().unwrap()
+        });
+
+        let mut joined = Remote::accept(&listener, Piece::X).unwrap();
+        let mut chat = Vec::new();
+        while chat.is_empty() {
+            chat = joined.take_chat();
+        }
+        assert_eq!(chat, vec!["good luck".to_string()]);
+
+        // Messages read while looking for chat are still handled in order
+        assert!(matches!(joined.make_move(&mut Board::new(), Piece::O), Action::Move(3)));
+        drop(joined);
+        assert_eq!(joiner.join().unwrap(), Message::Quit);
+    }
+}
@@ -0,0 +1,483 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::board::{Board, Piece};
+use crate::engine::{self, Engine};
+use crate::record::{self, GameRecord, GameResult, PlayerRecord};
+
+/// A game written in a PGN-like notation with tag pairs, numbered moves,
+/// comments, evals and move quality markers:
+///
+/// ```text
+/// [Event "Casual game"]
+/// [Date "2026.10.19"]
+/// [O "engine:10"]
+/// [X "human"]
+/// [Result "1-0"]
+///
+/// 1. 4 4 2. 4 {[%eval 120] builds a tower} 3? 3. 5!! 1-0
+/// ```
+///
+/// O moves first and plays the role of white, so `1-0` is a win for O.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnnotatedGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<AnnotatedMove>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnnotatedMove {
+    pub col: usize,
+    pub nag: Option<Nag>,
+    /// Engine eval after the move, from the point of view of the piece that played it
+    pub eval: Option<isize>,
+    pub comment: Option<String>,
+}
+
+/// Move quality markers, written as suffixes on the move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nag {
+    Good,
+    Mistake,
+    Brilliant,
+    Blunder,
+    Interesting,
+    Dubious,
+}
+
+const NAGS: [(&str, Nag); 6] = [
+    ("!!", Nag::Brilliant),
+    ("??", Nag::Blunder),
+    ("!?", Nag::Interesting),
+    ("?!", Nag::Dubious),
+    ("!", Nag::Good),
+    ("?", Nag::Mistake),
+];
+
+impl Nag {
+    pub fn symbol(&self) -> &'static str {
+        NAGS.iter()
+            .find(|(_, nag)| nag == self)
+            .map(|(symbol, _)| *symbol)
+            .expect("every nag has a symbol")
+    }
+
+    /// Numeric annotation glyphs as used by `$1` to `$6` in chess PGN
+    fn from_number(n: u32) -> Option<Nag> {
+        match n {
+            1 => Some(Nag::Good),
+            2 => Some(Nag::Mistake),
+            3 => Some(Nag::Brilliant),
+            4 => Some(Nag::Blunder),
+            5 => Some(Nag::Interesting),
+            6 => Some(Nag::Dubious),
+            _ => None,
+        }
+    }
+}
+
+impl AnnotatedMove {
+    pub fn new(col: usize) -> AnnotatedMove {
+        AnnotatedMove {
+            col,
+            nag: None,
+            eval: None,
+            comment: None,
+        }
+    }
+}
+
+fn result_token(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Ongoing => "*",
+        GameResult::Draw => "1/2-1/2",
+        GameResult::Win(Piece::O) | GameResult::Resigned(Piece::X) | GameResult::TimeForfeit(Piece::X) => "1-0",
+        GameResult::Win(_) | GameResult::Resigned(_) | GameResult::TimeForfeit(_) => "0-1",
+    }
+}
+
+/// Formats seconds since the Unix epoch as a PGN date like `2026.10.19`
+fn format_date(secs: u64) -> String {
+    // Converts days since the epoch to a civil date, from Howard Hinnant's
+    // `civil_from_days`
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+/// Reads a result token, with the `Termination` tag telling wins apart
+/// from resignations and losses on time
+fn parse_result(token: &str, termination: Option<&str>) -> GameResult {
+    let loser = match token {
+        "1-0" => Piece::X,
+        "0-1" => Piece::O,
+        "1/2-1/2" => return GameResult::Draw,
+        _ => return GameResult::Ongoing,
+    };
+
+    match termination {
+        Some("resignation") => GameResult::Resigned(loser),
+        Some("time forfeit") => GameResult::TimeForfeit(loser),
+        _ => GameResult::Win(loser.opponent()),
+    }
+}
+
+/// Parses a PGN date like `2026.10.19` into seconds since the Unix epoch,
+/// the inverse of [`format_date`]. Dates outside four digit years are not
+/// read.
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.split('.').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Howard Hinnant's `days_from_civil`
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    u64::try_from(days * 86_400).ok()
+}
+
+impl AnnotatedGame {
+    pub fn from_record(record: &GameRecord) -> AnnotatedGame {
+        let mut tags = vec![
+            ("Event".to_string(), "Casual game".to_string()),
+            ("Date".to_string(), format_date(record.started)),
+            ("O".to_string(), record.o.to_string()),
+            ("X".to_string(), record.x.to_string()),
+            ("Result".to_string(), result_token(record.result).to_string()),
+        ];
+        match record.result {
+            GameResult::Resigned(_) => tags.push(("Termination".to_string(), "resignation".to_string())),
+            GameResult::TimeForfeit(_) => tags.push(("Termination".to_string(), "time forfeit".to_string())),
+            _ => {}
+        }
+
+        AnnotatedGame {
+            tags,
+            moves: record.moves.iter().map(|&col| AnnotatedMove::new(col)).collect(),
+        }
+    }
+
+    /// Converts the game back into a record, reading players, result and
+    /// date from the tags where they are present
+    pub fn to_record(&self) -> Result<GameRecord, &'static str> {
+        let player = |name| match self.tag(name) {
+            Some(player) => player.parse(),
+            None => Ok(PlayerRecord::Human),
+        };
+
+        Ok(GameRecord {
+            version: record::VERSION,
+            x: player("X")?,
+            o: player("O")?,
+            first: Piece::O,
+            moves: self.columns(),
+            result: parse_result(self.tag("Result").unwrap_or("*"), self.tag("Termination")),
+            started: self.tag("Date").and_then(parse_date).unwrap_or(0),
+            finished: None,
+        })
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn columns(&self) -> Vec<usize> {
+        self.moves.iter().map(|m| m.col).collect()
+    }
+
+    /// Fills in the engine eval of every move and marks moves that threw
+    /// away a forced win as mistakes and moves into a forced loss as blunders
+    pub fn annotate(&mut self, engine: &Engine) {
+        let mut board = Board::new();
+
+        for annotated in &mut self.moves {
+            let scores = engine.analyze_all(&board);
+            let played = scores.iter().find(|score| score.col == annotated.col);
+
+            if let (Some(best), Some(played)) = (scores.first(), played) {
+                annotated.eval = Some(played.eval);
+
+                if annotated.nag.is_none() {
+                    let lost = played.exact && played.eval <= -engine::WIN_SCORE;
+                    let winning = best.exact && best.eval >= engine::WIN_SCORE;
+                    if lost && best.eval > played.eval {
+                        annotated.nag = Some(Nag::Blunder);
+                    } else if winning && played.eval < engine::WIN_SCORE {
+                        annotated.nag = Some(Nag::Mistake);
+                    }
+                }
+            }
+
+            let piece = board.to_move();
+            if board.insert_piece(annotated.col, piece).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl fmt::Display for AnnotatedGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{name} \"{value}\"]")?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        for (i, annotated) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                tokens.push(format!("{}.", i / 2 + 1));
+            }
+
+            let nag = annotated.nag.map(|nag| nag.symbol()).unwrap_or("");
+            tokens.push(format!("{}{nag}", annotated.col + 1));
+
+            let mut comment = Vec::new();
+            if let Some(eval) = annotated.eval {
+                comment.push(format!("[%eval {eval}]"));
+            }
+            if let Some(text) = &annotated.comment {
+                comment.push(text.replace('}', ")"));
+            }
+            if !comment.is_empty() {
+                tokens.push(format!("{{{}}}", comment.join(" ")));
+            }
+        }
+        tokens.push(self.tag("Result").unwrap_or("*").to_string());
+
+        // Wrap the move text at 80 columns like PGN exporters do
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                writeln!(f, "{line}")?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{line}")
+    }
+}
+
+/// Splits a comment into its `[%eval n]` command and the remaining text
+fn parse_comment(comment: &str) -> Result<(Option<isize>, Option<String>), &'static str> {
+    let mut eval = None;
+    let mut text = comment.to_string();
+
+    if let Some(start) = comment.find("[%eval") {
+        let end = comment[start..].find(']').ok_or("Unterminated eval in comment")? + start;
+        let value = comment[start + "[%eval".len()..end].trim();
+        eval = Some(value.parse().map_err(|_| "Eval must be a number")?);
+        text = format!("{} {}", &comment[..start], &comment[end + 1..]);
+    }
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    Ok((eval, (!text.is_empty()).then_some(text)))
+}
+
+fn parse_move(token: &str) -> Result<AnnotatedMove, &'static str> {
+    let mut chars = token.chars();
+    let col = match chars.next().and_then(|c| c.to_digit(10)) {
+        Some(d @ 1..=7) => d as usize - 1,
+        _ => return Err("Moves must be column numbers from 1 to 7"),
+    };
+
+    let suffix = chars.as_str();
+    let nag = if suffix.is_empty() {
+        None
+    } else {
+        let nag = NAGS.iter().find(|(symbol, _)| *symbol == suffix);
+        Some(nag.ok_or("Unknown move marker")?.1)
+    };
+
+    Ok(AnnotatedMove { nag, ..AnnotatedMove::new(col) })
+}
+
+impl FromStr for AnnotatedGame {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut game = AnnotatedGame::default();
+        let mut rest = s;
+
+        loop {
+            rest = rest.trim_start();
+            let Some(c) = rest.chars().next() else {
+                break;
+            };
+
+            match c {
+                '[' => {
+                    let (name, after) = rest[1..].trim_start().split_once('"').ok_or("Tag is missing its value")?;
+                    let mut value = String::new();
+                    let mut chars = after.char_indices();
+                    let end = loop {
+                        match chars.next().ok_or("Unterminated tag value")? {
+                            (_, '\\') => value.extend(chars.next().map(|(_, c)| c)),
+                            (i, '"') => break i,
+                            (_, c) => value.push(c),
+                        }
+                    };
+                    let after = after[end + 1..].trim_start();
+                    rest = after.strip_prefix(']').ok_or("Tag is missing its closing bracket")?;
+                    game.tags.push((name.trim().to_string(), value));
+                }
+                '{' => {
+                    let end = rest.find('}').ok_or("Unterminated comment")?;
+                    let (eval, comment) = parse_comment(&rest[1..end])?;
+                    let annotated = game.moves.last_mut().ok_or("Comment before the first move")?;
+                    annotated.eval = eval.or(annotated.eval);
+                    annotated.comment = comment.or(annotated.comment.take());
+                    rest = &rest[end + 1..];
+                }
+                ';' => {
+                    // Rest of line comments are skipped
+                    rest = rest.split_once('\n').map(|(_, after)| after).unwrap_or("");
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || c == '{' || c == '[' || c == ';')
+                        .unwrap_or(rest.len());
+                    let token = &rest[..end];
+                    rest = &rest[end..];
+
+                    if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+                        break;
+                    }
+                    if let Some(number) = token.strip_prefix('$') {
+                        let nag = number.parse().ok().and_then(Nag::from_number).ok_or("Unknown move marker")?;
+                        game.moves.last_mut().ok_or("Marker before the first move")?.nag = Some(nag);
+                        continue;
+                    }
+                    // Move numbers like `12.` only help humans read the game
+                    if token.ends_with('.') && token.trim_end_matches('.').chars().all(|c| c.is_ascii_digit()) {
+                        continue;
+                    }
+
+                    game.moves.push(parse_move(token)?);
+                }
+            }
+        }
+
+        Board::from_moves(&game.columns())?;
+
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod pgn_tests {
+    use super::*;
+
+    const GAME: &str = r#"[Event "Team \"review\""]
+[O "engine:10"]
+[X "human"]
+[Result "*"]
+
+1. 4 4 2. 4 {[%eval 120] builds a tower} 3? 3. 4!! $4 *
+"#;
+
+    #[test]
+    fn parses_tags_moves_and_annotations() {
+        let game: AnnotatedGame = GAME.parse().unwrap();
+
+        assert_eq!(game.tag("Event"), Some("Team \"review\""));
+        assert_eq!(game.tag("Result"), Some("*"));
+        assert_eq!(game.columns(), vec![3, 3, 3, 2, 3]);
+        assert_eq!(game.moves[2].eval, Some(120));
+        assert_eq!(game.moves[2].comment.as_deref(), Some("builds a tower"));
+        assert_eq!(game.moves[3].nag, Some(Nag::Mistake));
+        assert_eq!(game.moves[4].nag, Some(Nag::Blunder));
+    }
+
+    #[test]
+    fn writer_round_trips() {
+        let game: AnnotatedGame = GAME.parse().unwrap();
+
+        let written = game.to_string();
+
+        assert_eq!(written.parse::<AnnotatedGame>().unwrap(), game);
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        assert!("1. 1 1 2. 1 1 3. 1 1 4. 1".parse::<AnnotatedGame>().is_err());
+        assert!("1. 8".parse::<AnnotatedGame>().is_err());
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970.01.01");
+        assert_eq!(format_date(1_792_368_000), "2026.10.19");
+        assert_eq!(parse_date("2026.10.19"), Some(1_792_368_000));
+        assert_eq!(parse_date("9999999999999999.1.1"), None);
+        assert_eq!(parse_date("2026.13.1"), None);
+        assert_eq!(parse_date("1969.12.31"), None);
+    }
+
+    #[test]
+    fn annotate_marks_blunders() {
+        // X lets O complete the bottom row instead of blocking
+        let record = GameRecord {
+            version: record::VERSION,
+            x: PlayerRecord::Human,
+            o: PlayerRecord::Human,
+            first: Piece::O,
+            moves: vec![0, 6, 1, 6, 2, 5],
+            result: GameResult::Ongoing,
+            started: 0,
+            finished: None,
+        };
+        let mut game = AnnotatedGame::from_record(&record);
+
+        game.annotate(&Engine { depth: 4, ..Engine::default() });
+
+        assert!(game.moves.iter().all(|annotated| annotated.eval.is_some()));
+        assert_eq!(game.moves[5].nag, Some(Nag::Blunder));
+        assert!(game.moves[..5].iter().all(|annotated| annotated.nag != Some(Nag::Blunder)));
+        assert!(game.to_string().contains("6??"));
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let mut game: AnnotatedGame = GAME.parse().unwrap();
+        game.set_tag("Result", "0-1");
+        game.set_tag("Termination", "resignation");
+
+        let record = game.to_record().unwrap();
+
+        assert_eq!(record.o, PlayerRecord::Engine { depth: 10 });
+        assert_eq!(record.result, GameResult::Resigned(Piece::O));
+        assert_eq!(AnnotatedGame::from_record(&record).columns(), game.columns());
+    }
+}
@@ -0,0 +1,120 @@
+//! Thinking on the opponent's time. While a human thinks about their move,
+//! the engine searches the positions after each of their replies in a
+//! background thread, so when the move comes the answer is often ready.
+//!
+//! Connect Four never has more than seven replies, so instead of guessing
+//! one the engine ponders all of them, the likeliest first.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::board::Board;
+use crate::engine::Engine;
+
+/// Depth of the quick search that guesses which replies are likeliest
+const GUESS_DEPTH: u32 = 4;
+
+/// Each reply searched so far, with the column and eval the engine would
+/// answer it with
+type Answers = Mutex<Vec<(usize, (usize, isize))>>;
+
+/// A background search of the replies to one position
+pub struct Ponder {
+    moves: Vec<usize>,
+    stop: Arc<AtomicBool>,
+    answers: Arc<Answers>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Ponder {
+    /// Starts pondering the position after `moves`, where the opponent of
+    /// `engine` is to move
+    pub fn start(engine: &Engine, moves: &[usize]) -> Ponder {
+        let stop = Arc::new(AtomicBool::new(false));
+        let answers = Arc::new(Mutex::new(Vec::new()));
+
+        let board = Board::from_moves(moves).expect("pondered positions are legal");
+        let engine = engine.clone();
+        let thread = {
+            let (stop, answers) = (stop.clone(), answers.clone());
+            thread::spawn(move || ponder(&engine, board, &stop, &answers))
+        };
+
+        Ponder {
+            moves: moves.to_vec(),
+            stop,
+            answers,
+            thread: Some(thread),
+        }
+    }
+
+    /// The moves leading to the pondered position
+    pub fn moves(&self) -> &[usize] {
+        &self.moves
+    }
+
+    /// Stops pondering and returns the engine's move and eval after
+    /// `reply`, if that reply was searched in time
+    pub fn finish(mut self, reply: usize) -> Option<(usize, isize)> {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+
+        let answers = self.answers.lock().expect("ponder thread does not panic");
+        answers.iter().find(|(col, _)| *col == reply).map(|&(_, answer)| answer)
+    }
+}
+
+impl Drop for Ponder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Searches the answer to every reply like [`Engine::best_move`] would at
+/// the engine's full depth, so pondering never makes it weaker
+fn ponder(engine: &Engine, mut board: Board, stop: &AtomicBool, answers: &Answers) {
+    let replies = Engine { depth: GUESS_DEPTH, ..Engine::default() }.analyze_all(&board);
+
+    for reply in replies {
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        board.insert_piece(reply.col, board.to_move()).expect("analyzed columns are legal");
+
+        if board.is_terminal().is_none()
+            && let Some(answer) = engine.best_move_until(&board, stop)
+        {
+            answers.lock().expect("answers are only locked briefly").push((reply.col, answer));
+        }
+
+        board.undo_move(reply.col);
+    }
+}
+
+#[cfg(test)]
+mod ponder_tests {
+    use super::*;
+
+    #[test]
+    fn answers_match_a_normal_search() {
+        let engine = Engine { depth: 3, ..Engine::default() };
+        let ponder = Ponder::start(&engine, &[3]);
+        while ponder.answers.lock().unwrap().len() < 7 {
+            thread::yield_now();
+        }
+
+        let answer = ponder.finish(2);
+
+        assert_eq!(answer, Some(engine.best_move(&Board::from_moves(&[3, 2]).unwrap())));
+    }
+
+    #[test]
+    fn stopping_early_leaves_no_answer() {
+        let ponder = Ponder::start(&Engine { depth: 42, ..Engine::default() }, &[]);
+
+        assert_eq!(ponder.finish(3), None);
+    }
+}
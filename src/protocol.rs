@@ -0,0 +1,416 @@
+//! A text protocol modelled on UCI, so GUIs, match runners and programs in
+//! other languages can use the engine through stdin and stdout.
+//!
+//! ```text
+//! uci                          -> id name ..., option ..., uciok
+//! isready                      -> readyok
+//! setoption name Depth value 12
+//! setoption name Ordering value hash,killers,history
+//! setoption name Driver value mtdf   alphabeta by default
+//! ucinewgame
+//! position startpos
+//! position moves 4453          columns from 1 to 7, spaces are optional
+//! go depth 12 | go movetime 1000 | go infinite | go
+//!                              -> info depth 1 score cp 12 nodes 8 time 0 pv 4
+//!                              -> bestmove 4
+//! stop                         ends the search, which still answers bestmove
+//!                              and is the only way go infinite answers
+//! quit
+//! ```
+//!
+//! `go` always answers with a `bestmove`. When there is no move to play,
+//! because the game is over or the last `position` was illegal, it is
+//! `bestmove 0000` after an `info string` saying why. An illegal position
+//! is not replaced by the one before it, so searches wait for a legal one.
+//!
+//! Scores are from the point of view of the side to move. Forced results
+//! are written as `score mate N` with N in moves, negative when losing.
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::board::{self, Board};
+use crate::engine::{Engine, Limits, MoveOrdering, SearchInfo};
+
+/// Deepest search that can be asked for, enough to fill the board
+const MAX_DEPTH: u32 = 42;
+
+/// How often an infinite search that has finished looks for `stop`
+const STOP_POLL: Duration = Duration::from_millis(10);
+
+/// A command sent to the engine
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Uci,
+    IsReady,
+    SetOption { name: String, value: String },
+    NewGame,
+    Position(Vec<usize>),
+    Go(Go),
+    Stop,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Go {
+    Limits(Limits),
+    /// Search until told to stop
+    Infinite,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, &'static str> {
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("uci") => Ok(Command::Uci),
+            Some("isready") => Ok(Command::IsReady),
+            Some("ucinewgame") => Ok(Command::NewGame),
+            Some("stop") => Ok(Command::Stop),
+            Some("quit") => Ok(Command::Quit),
+            Some("setoption") => {
+                let rest: Vec<&str> = words.collect();
+                match rest.iter().position(|&word| word == "value") {
+                    Some(i) if rest.first() == Some(&"name") && i > 1 => Ok(Command::SetOption {
+                        name: rest[1..i].join(" "),
+                        value: rest[i + 1..].join(" "),
+                    }),
+                    _ => Err("Expected setoption name <name> value <value>"),
+                }
+            }
+            Some("position") => {
+                let rest: Vec<&str> = words.skip_while(|&word| word == "startpos").collect();
+                match rest.split_first() {
+                    None => Ok(Command::Position(Vec::new())),
+                    Some((&"moves", moves)) => Ok(Command::Position(board::parse_moves(&moves.concat())?)),
+                    Some(_) => Err("Expected position startpos or position moves <moves>"),
+                }
+            }
+            Some("go") => Command::parse_go(words),
+            _ => Err("Unknown command"),
+        }
+    }
+
+    fn parse_go<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<Command, &'static str> {
+        let mut limits = Limits::default();
+
+        while let Some(word) = words.next() {
+            let mut number = || words.next().and_then(|n| n.parse::<u64>().ok());
+            match word {
+                "infinite" => return Ok(Command::Go(Go::Infinite)),
+                "depth" => {
+                    let depth = number().filter(|&depth| depth > 0).ok_or("Depth must be a positive number")?;
+                    limits.depth = Some(depth.min(MAX_DEPTH as u64) as u32);
+                }
+                "movetime" => {
+                    let ms = number().ok_or("Movetime must be a number of milliseconds")?;
+                    limits.movetime = Some(Duration::from_millis(ms));
+                }
+                _ => return Err("Expected go depth <n>, go movetime <ms> or go infinite"),
+            }
+        }
+
+        Ok(Command::Go(Go::Limits(limits)))
+    }
+}
+
+/// An `info` line for a finished depth of the search
+pub fn info_line(info: &SearchInfo) -> String {
+    let score = match info.plies_to_end() {
+        Some(plies) if info.eval > 0 => format!("mate {}", plies.div_ceil(2)),
+        Some(plies) => format!("mate -{}", plies.div_ceil(2)),
+        None => format!("cp {}", info.eval),
+    };
+
+    format!(
+        "info depth {} score {score} nodes {} time {} pv {}",
+        info.depth,
+        info.nodes,
+        info.time.as_millis(),
+        info.best_move + 1
+    )
+}
+
+/// A search running on its own thread, so `stop` can still be read
+struct Running {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    /// Infinite searches hold back their `bestmove` until they are stopped
+    infinite: bool,
+}
+
+/// The engine's side of a conversation with a GUI
+pub struct Session<W: Write + Send + 'static> {
+    engine: Engine,
+    /// `None` after a `position` that could not be set up
+    board: Option<Board>,
+    out: Arc<Mutex<W>>,
+    search: Option<Running>,
+}
+
+impl<W: Write + Send + 'static> Session<W> {
+    pub fn new(out: W) -> Session<W> {
+        Session {
+            engine: Engine::default(),
+            board: Some(Board::new()),
+            out: Arc::new(Mutex::new(out)),
+            search: None,
+        }
+    }
+
+    fn send(&self, line: &str) -> io::Result<()> {
+        send(&self.out, line)
+    }
+
+    /// Handles one line of input, returning `false` once the GUI quits
+    pub fn handle(&mut self, line: &str) -> io::Result<bool> {
+        if line.trim().is_empty() {
+            return Ok(true);
+        }
+
+        let command = match Command::parse(line) {
+            Ok(command) => command,
+            Err(e) => {
+                if line.split_whitespace().next() == Some("position") {
+                    self.stop();
+                    self.board = None;
+                }
+                self.send(&format!("info string {e}"))?;
+                return Ok(true);
+            }
+        };
+
+        match command {
+            Command::Uci => {
+                self.send(concat!("id name four-in-a-row ", env!("CARGO_PKG_VERSION")))?;
+                self.send(&format!(
+                    "option name Depth type spin default {} min 1 max {MAX_DEPTH}",
+                    Engine::default().depth
+                ))?;
+                self.send(&format!("option name Ordering type string default {}", MoveOrdering::default()))?;
+                self.send("option name Driver type combo default alphabeta var alphabeta var mtdf")?;
+                self.send("uciok")?;
+            }
+            Command::IsReady => self.send("readyok")?,
+            Command::SetOption { name, value } => self.set_option(&name, &value)?,
+            Command::NewGame => {
+                self.stop();
+                self.board = Some(Board::new());
+            }
+            Command::Position(moves) => {
+                self.stop();
+                self.board = Board::from_moves(&moves).ok();
+                if self.board.is_none() {
+                    self.send("info string Illegal position, there is no position to search until the next one")?;
+                }
+            }
+            Command::Go(go) => self.go(go)?,
+            Command::Stop => self.stop(),
+            Command::Quit => {
+                self.stop();
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        match (name, value.parse::<u32>()) {
+            ("Depth", Ok(depth @ 1..=MAX_DEPTH)) => {
+                self.engine.depth = depth;
+                Ok(())
+            }
+            ("Depth", _) => self.send(&format!("info string Depth must be from 1 to {MAX_DEPTH}")),
+            ("Ordering", _) => match value.parse() {
+                Ok(ordering) => {
+                    self.engine.ordering = ordering;
+                    Ok(())
+                }
+                Err(e) => self.send(&format!("info string {e}")),
+            },
+            ("Driver", _) => match value.parse() {
+                Ok(driver) => {
+                    self.engine.driver = driver;
+                    Ok(())
+                }
+                Err(e) => self.send(&format!("info string {e}")),
+            },
+            _ => self.send(&format!("info string Unknown option {name}")),
+        }
+    }
+
+    fn go(&mut self, go: Go) -> io::Result<()> {
+        self.stop();
+
+        let board = match &self.board {
+            Some(board) if board.is_terminal().is_some() => return self.send_no_move("The game is over"),
+            Some(board) => board.clone(),
+            None => return self.send_no_move("No legal position has been set"),
+        };
+
+        let limits = match go {
+            Go::Limits(limits) => limits,
+            Go::Infinite => Limits {
+                depth: Some(MAX_DEPTH),
+                movetime: None,
+            },
+        };
+        let infinite = go == Go::Infinite;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (engine, out) = (self.engine.clone(), self.out.clone());
+        let flag = stop.clone();
+
+        let handle = thread::spawn(move || {
+            let best = engine.search(&board, &limits, &flag, |info| {
+                send(&out, &info_line(info)).ok();
+            });
+            // A forced result or a full board ends the search early
+            while infinite && !flag.load(Ordering::Relaxed) {
+                thread::sleep(STOP_POLL);
+            }
+            send(&out, &format!("bestmove {}", best.best_move + 1)).ok();
+        });
+
+        self.search = Some(Running { stop, handle, infinite });
+        Ok(())
+    }
+
+    /// Answers a `go` that has nothing to search, so GUIs waiting for a
+    /// `bestmove` still get one
+    fn send_no_move(&self, reason: &str) -> io::Result<()> {
+        self.send(&format!("info string {reason}"))?;
+        self.send("bestmove 0000")
+    }
+
+    /// Stops the running search, if any, and waits for its `bestmove`
+    fn stop(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::Relaxed);
+            search.handle.join().ok();
+        }
+    }
+}
+
+fn send<W: Write>(out: &Mutex<W>, line: &str) -> io::Result<()> {
+    let mut out = out.lock().expect("output lock poisoned");
+    writeln!(out, "{line}")?;
+    out.flush()
+}
+
+/// Speaks the protocol until `quit` or the end of the input
+pub fn run<W: Write + Send + 'static>(input: impl BufRead, output: W) -> io::Result<()> {
+    let mut session = Session::new(output);
+
+    for line in input.lines() {
+        if !session.handle(&line?)? {
+            return Ok(());
+        }
+    }
+
+    // Let a search that was started last finish before leaving, unless it
+    // would only end with a stop that is never coming
+    if let Some(search) = session.search.take() {
+        if search.infinite {
+            search.stop.store(true, Ordering::Relaxed);
+        }
+        search.handle.join().ok();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod protocol_tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A writer whose contents can be read after the session is done with it
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn converse(script: &str) -> String {
+        let output = Shared::default();
+        run(Cursor::new(script.to_string()), output.clone()).unwrap();
+        let bytes = output.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("position moves 4453"), Ok(Command::Position(vec![3, 3, 4, 2])));
+        assert_eq!(Command::parse("position startpos moves 4 4"), Ok(Command::Position(vec![3, 3])));
+        assert_eq!(
+            Command::parse("go depth 12"),
+            Ok(Command::Go(Go::Limits(Limits { depth: Some(12), movetime: None })))
+        );
+        assert_eq!(
+            Command::parse("setoption name Depth value 6"),
+            Ok(Command::SetOption { name: "Depth".to_string(), value: "6".to_string() })
+        );
+        assert!(Command::parse("go depth").is_err());
+    }
+
+    #[test]
+    fn finds_the_winning_move() {
+        let output = converse("uci\nisready\nposition moves 112233\ngo depth 4\n");
+
+        assert!(output.contains("uciok\nreadyok\n"));
+        assert!(output.contains("info depth 1 score mate 1"));
+        assert!(output.ends_with("bestmove 4\n"));
+    }
+
+    #[test]
+    fn stop_still_answers_bestmove() {
+        let output = converse("position startpos\ngo infinite\nstop\nquit\n");
+
+        assert_eq!(output.matches("bestmove").count(), 1);
+    }
+
+    #[test]
+    fn infinite_searches_answer_only_after_stop() {
+        let output = Shared::default();
+        let mut session = Session::new(output.clone());
+        let written = || String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+
+        // The mate is found at once, but the answer waits for stop
+        session.handle("position moves 112233").unwrap();
+        session.handle("go infinite").unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(written().contains("score mate 1"));
+        assert!(!written().contains("bestmove"));
+
+        session.handle("stop").unwrap();
+        assert!(written().ends_with("bestmove 4\n"));
+    }
+
+    #[test]
+    fn always_answers_go() {
+        let over = converse("position moves 1212121\ngo depth 4\n");
+        assert!(over.ends_with("info string The game is over\nbestmove 0000\n"));
+
+        // An illegal position does not leave the previous one in place
+        let illegal = converse("position moves 4453\nposition moves 1111111\ngo depth 4\n");
+        assert!(illegal.contains("Illegal position"));
+        assert!(illegal.ends_with("bestmove 0000\n"));
+
+        let unreadable = converse("position moves 4453\nposition moves 48\ngo depth 4\nposition startpos\ngo depth 1\n");
+        assert!(unreadable.contains("bestmove 0000\n"));
+        assert_eq!(unreadable.matches("bestmove").count(), 2);
+    }
+}
@@ -0,0 +1,240 @@
+//! A tiny software rasteriser for the shapes used in board images, so that
+//! PNG and GIF output needs no font files or graphics libraries.
+
+/// An opaque colour
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// The colour as `#rrggbb` for SVG
+    pub fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// Everything that can be drawn on a board image. Coordinates are in pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Rect { x: f32, y: f32, w: f32, h: f32, color: Rgb },
+    Circle { cx: f32, cy: f32, r: f32, color: Rgb },
+    Ring { cx: f32, cy: f32, r: f32, width: f32, color: Rgb },
+    Line { from: (f32, f32), to: (f32, f32), width: f32, color: Rgb },
+    Triangle { points: [(f32, f32); 3], color: Rgb },
+    /// Digits centred on a point, with `size` as the height of a digit
+    Text { x: f32, y: f32, size: f32, text: String, color: Rgb },
+}
+
+/// 3x5 bitmaps of the digits 0 to 9, one row per entry with the top row first
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Samples per pixel along each axis, used for anti-aliasing
+const SAMPLES: usize = 4;
+
+/// An RGB image in row-major order
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Shape {
+    /// Pixel bounds `(x0, y0, x1, y1)` that the shape can touch
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        match self {
+            Shape::Rect { x, y, w, h, .. } => (*x, *y, x + w, y + h),
+            Shape::Circle { cx, cy, r, .. } => (cx - r, cy - r, cx + r, cy + r),
+            Shape::Ring { cx, cy, r, width, .. } => {
+                let r = r + width / 2.0;
+                (cx - r, cy - r, cx + r, cy + r)
+            }
+            Shape::Line { from, to, width, .. } => (
+                from.0.min(to.0) - width,
+                from.1.min(to.1) - width,
+                from.0.max(to.0) + width,
+                from.1.max(to.1) + width,
+            ),
+            Shape::Triangle { points, .. } => {
+                let xs = points.map(|p| p.0);
+                let ys = points.map(|p| p.1);
+                (
+                    xs.iter().cloned().fold(f32::MAX, f32::min),
+                    ys.iter().cloned().fold(f32::MAX, f32::min),
+                    xs.iter().cloned().fold(f32::MIN, f32::max),
+                    ys.iter().cloned().fold(f32::MIN, f32::max),
+                )
+            }
+            Shape::Text { x, y, size, text, .. } => {
+                let w = text_width(text, *size);
+                (x - w / 2.0, y - size / 2.0, x + w / 2.0, y + size / 2.0)
+            }
+        }
+    }
+
+    fn contains(&self, px: f32, py: f32) -> bool {
+        match self {
+            Shape::Rect { x, y, w, h, .. } => px >= *x && px < x + w && py >= *y && py < y + h,
+            Shape::Circle { cx, cy, r, .. } => (px - cx).powi(2) + (py - cy).powi(2) <= r * r,
+            Shape::Ring { cx, cy, r, width, .. } => {
+                let d = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+                (d - r).abs() <= width / 2.0
+            }
+            Shape::Line { from, to, width, .. } => distance_to_segment((px, py), *from, *to) <= width / 2.0,
+            Shape::Triangle { points: [a, b, c], .. } => {
+                let side = |p: (f32, f32), q: (f32, f32)| (q.0 - p.0) * (py - p.1) - (q.1 - p.1) * (px - p.0);
+                let (d1, d2, d3) = (side(*a, *b), side(*b, *c), side(*c, *a));
+                let negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+                let positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+                !(negative && positive)
+            }
+            Shape::Text { x, y, size, text, .. } => {
+                let scale = size / 5.0;
+                let left = x - text_width(text, *size) / 2.0;
+                let top = y - size / 2.0;
+                if py < top || py >= top + size || px < left {
+                    return false;
+                }
+
+                // Each digit is three cells wide with one cell of spacing
+                let cell_x = ((px - left) / scale) as usize;
+                let cell_y = ((py - top) / scale) as usize;
+                let (index, column) = (cell_x / 4, cell_x % 4);
+                let Some(digit) = text.chars().nth(index).and_then(|c| c.to_digit(10)) else {
+                    return false;
+                };
+
+                column < 3 && DIGITS[digit as usize][cell_y.min(4)] & (0b100 >> column) != 0
+            }
+        }
+    }
+
+    fn color(&self) -> Rgb {
+        match self {
+            Shape::Rect { color, .. }
+            | Shape::Circle { color, .. }
+            | Shape::Ring { color, .. }
+            | Shape::Line { color, .. }
+            | Shape::Triangle { color, .. }
+            | Shape::Text { color, .. } => *color,
+        }
+    }
+}
+
+fn text_width(text: &str, size: f32) -> f32 {
+    let digits = text.chars().count() as f32;
+    (digits * 4.0 - 1.0) * size / 5.0
+}
+
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0)
+    };
+    let (x, y) = (a.0 + t * dx, a.1 + t * dy);
+
+    ((p.0 - x).powi(2) + (p.1 - y).powi(2)).sqrt()
+}
+
+impl Canvas {
+    /// A white canvas, or an error if its pixels would not fit in memory
+    pub fn new(width: u32, height: u32) -> Result<Canvas, &'static str> {
+        let bytes = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or("Image is too large")?;
+
+        Ok(Canvas {
+            width,
+            height,
+            pixels: vec![255; bytes],
+        })
+    }
+
+    /// Draws the shapes in order, later shapes on top
+    pub fn draw(&mut self, shapes: &[Shape]) {
+        for shape in shapes {
+            self.draw_shape(shape);
+        }
+    }
+
+    fn draw_shape(&mut self, shape: &Shape) {
+        let (x0, y0, x1, y1) = shape.bounds();
+        let x0 = x0.floor().max(0.0) as u32;
+        let y0 = y0.floor().max(0.0) as u32;
+        let x1 = (x1.ceil() as u32).min(self.width);
+        let y1 = (y1.ceil() as u32).min(self.height);
+        let color = shape.color();
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let mut hits = 0;
+                for sy in 0..SAMPLES {
+                    for sx in 0..SAMPLES {
+                        let px = x as f32 + (sx as f32 + 0.5) / SAMPLES as f32;
+                        let py = y as f32 + (sy as f32 + 0.5) / SAMPLES as f32;
+                        if shape.contains(px, py) {
+                            hits += 1;
+                        }
+                    }
+                }
+                if hits > 0 {
+                    self.blend(x, y, color, hits as f32 / (SAMPLES * SAMPLES) as f32);
+                }
+            }
+        }
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: Rgb, coverage: f32) {
+        let i = ((y * self.width + x) * 3) as usize;
+        for (channel, value) in [color.0, color.1, color.2].into_iter().enumerate() {
+            let old = self.pixels[i + channel] as f32;
+            self.pixels[i + channel] = (old + (value as f32 - old) * coverage).round() as u8;
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Rgb {
+        let i = ((y * self.width + x) * 3) as usize;
+        Rgb(self.pixels[i], self.pixels[i + 1], self.pixels[i + 2])
+    }
+}
+
+#[cfg(test)]
+mod raster_tests {
+    use super::*;
+
+    #[test]
+    fn circle_covers_its_centre_only() {
+        let mut canvas = Canvas::new(20, 20).unwrap();
+        let red = Rgb(255, 0, 0);
+
+        canvas.draw(&[Shape::Circle { cx: 10.0, cy: 10.0, r: 5.0, color: red }]);
+
+        assert_eq!(canvas.pixel(10, 10), red);
+        assert_eq!(canvas.pixel(1, 1), Rgb(255, 255, 255));
+    }
+
+    #[test]
+    fn text_draws_digit_strokes() {
+        let mut canvas = Canvas::new(20, 20).unwrap();
+        let black = Rgb(0, 0, 0);
+
+        // A 1 has its stem in the middle column and nothing in the top left
+        canvas.draw(&[Shape::Text { x: 10.0, y: 10.0, size: 10.0, text: "1".to_string(), color: black }]);
+
+        assert_eq!(canvas.pixel(10, 10), black);
+        assert_eq!(canvas.pixel(7, 5), Rgb(255, 255, 255));
+    }
+}
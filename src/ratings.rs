@@ -0,0 +1,279 @@
+//! Ratings for everyone who plays, kept in a JSON file and updated after
+//! every finished game, so engine depths can be compared with each other
+//! and with the people playing them.
+//!
+//! Every player has an Elo rating and a Glicko-2 rating. Glicko-2 also
+//! tracks how sure it is of a rating, which makes it settle faster for new
+//! players. Each game counts as its own rating period.
+
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::arena;
+use crate::board::Piece;
+use crate::record::{GameResult, PlayerRecord};
+
+/// Where every rating starts, in both systems
+pub const INITIAL_RATING: f64 = 1500.0;
+/// How far one game can move an Elo rating
+const K: f64 = 32.0;
+const INITIAL_DEVIATION: f64 = 350.0;
+const INITIAL_VOLATILITY: f64 = 0.06;
+/// How much the volatility may change, between 0.3 and 1.2 in the paper
+const TAU: f64 = 0.5;
+/// Converts between the Glicko and Glicko-2 scales
+const SCALE: f64 = 173.7178;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Glicko {
+    pub rating: f64,
+    /// How uncertain the rating is. The true rating is within twice this
+    /// of `rating` 95% of the time.
+    pub deviation: f64,
+    /// How erratic the player's results are
+    pub volatility: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    pub elo: f64,
+    pub glicko: Glicko,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ratings {
+    pub players: BTreeMap<String, Rating>,
+}
+
+impl Default for Glicko {
+    fn default() -> Self {
+        Glicko {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+            volatility: INITIAL_VOLATILITY,
+        }
+    }
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            elo: INITIAL_RATING,
+            glicko: Glicko::default(),
+            wins: 0,
+            draws: 0,
+            losses: 0,
+        }
+    }
+}
+
+/// The name a player is rated under. Humans at this computer go by `human`,
+/// the name they gave, and remote players by their address without the port, which changes
+/// from game to game.
+pub fn player_name(player: &PlayerRecord, human: &str) -> String {
+    match player {
+        PlayerRecord::Human => human.to_string(),
+        PlayerRecord::Remote { address } => {
+            let host = address.rsplit_once(':').map_or(address.as_str(), |(host, _)| host);
+            format!("remote:{host}")
+        }
+        _ => player.to_string(),
+    }
+}
+
+/// The share of points a player rated `elo` is expected to score against
+/// one rated `opponent`
+pub fn expected_score(elo: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - elo) / 400.0))
+}
+
+impl Glicko {
+    /// The rating after scoring `score` against `opponent`, following
+    /// Glickman's description of Glicko-2
+    pub fn updated(&self, opponent: &Glicko, score: f64) -> Glicko {
+        let mu = (self.rating - INITIAL_RATING) / SCALE;
+        let phi = self.deviation / SCALE;
+        let mu_j = (opponent.rating - INITIAL_RATING) / SCALE;
+        let phi_j = opponent.deviation / SCALE;
+
+        let g = 1.0 / (1.0 + 3.0 * phi_j.powi(2) / PI.powi(2)).sqrt();
+        let expected = 1.0 / (1.0 + (-g * (mu - mu_j)).exp());
+        let v = 1.0 / (g.powi(2) * expected * (1.0 - expected));
+        let delta = v * g * (score - expected);
+
+        // The new volatility is the root of f, found with the Illinois method
+        let a = self.volatility.powi(2).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta.powi(2) - phi.powi(2) - v - ex) / (2.0 * (phi.powi(2) + v + ex).powi(2)) - (x - a) / TAU.powi(2)
+        };
+        let mut low = a;
+        let mut high = if delta.powi(2) > phi.powi(2) + v {
+            (delta.powi(2) - phi.powi(2) - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+        let (mut f_low, mut f_high) = (f(low), f(high));
+        while (high - low).abs() > 1e-6 {
+            let c = low + (low - high) * f_low / (f_high - f_low);
+            let f_c = f(c);
+            if f_c * f_high <= 0.0 {
+                low = high;
+                f_low = f_high;
+            } else {
+                f_low /= 2.0;
+            }
+            high = c;
+            f_high = f_c;
+        }
+        let volatility = (low / 2.0).exp();
+
+        let phi_star = (phi.powi(2) + volatility.powi(2)).sqrt();
+        let phi = 1.0 / (1.0 / phi_star.powi(2) + 1.0 / v).sqrt();
+        let mu = mu + phi.powi(2) * g * (score - expected);
+
+        Glicko {
+            rating: mu * SCALE + INITIAL_RATING,
+            deviation: phi * SCALE,
+            volatility,
+        }
+    }
+}
+
+impl Ratings {
+    /// Reads the ratings in `path`, or starts with none if there is no
+    /// such file yet
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Ratings> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(io::Error::from),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Ratings::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// Updates both players' ratings for a game `o` and `x` played. Games
+    /// that did not finish are not counted, and `false` is returned.
+    pub fn record_game(&mut self, o: &str, x: &str, result: GameResult) -> bool {
+        if result == GameResult::Ongoing || o == x {
+            return false;
+        }
+        let score = match arena::winner(result) {
+            Some(Piece::O) => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+
+        let o_rating = self.players.get(o).copied().unwrap_or_default();
+        let x_rating = self.players.get(x).copied().unwrap_or_default();
+
+        for (name, rating, opponent, score) in [(o, o_rating, x_rating, score), (x, x_rating, o_rating, 1.0 - score)] {
+            let entry = self.players.entry(name.to_string()).or_default();
+            entry.elo = rating.elo + K * (score - expected_score(rating.elo, opponent.elo));
+            entry.glicko = rating.glicko.updated(&opponent.glicko, score);
+            match score {
+                1.0 => entry.wins += 1,
+                0.0 => entry.losses += 1,
+                _ => entry.draws += 1,
+            }
+        }
+
+        true
+    }
+
+    /// Loads the ratings in `path`, records a game and saves them again
+    pub fn record_in(path: impl AsRef<Path>, o: &str, x: &str, result: GameResult) -> io::Result<()> {
+        let mut ratings = Ratings::load(&path)?;
+        if ratings.record_game(o, x, result) {
+            ratings.save(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Everyone ranked by Glicko-2 rating
+    pub fn leaderboard(&self) -> String {
+        let mut players: Vec<(&String, &Rating)> = self.players.iter().collect();
+        players.sort_by(|(_, a), (_, b)| b.glicko.rating.total_cmp(&a.glicko.rating));
+        let width = players.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("Player".len());
+
+        let mut table = format!("{:>3}  {:<width$}  {:>5}  {:>11}  {:>5}  {:>5}  {:>5}\n", "", "Player", "Elo", "Glicko-2", "Won", "Drawn", "Lost");
+        for (rank, (name, rating)) in players.iter().enumerate() {
+            let glicko = format!("{:.0} ±{:.0}", rating.glicko.rating, 2.0 * rating.glicko.deviation);
+            let _ = writeln!(
+                table,
+                "{:>3}. {name:<width$}  {:>5.0}  {glicko:>11}  {:>5}  {:>5}  {:>5}",
+                rank + 1,
+                rating.elo,
+                rating.wins,
+                rating.draws,
+                rating.losses
+            );
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod ratings_tests {
+    use super::*;
+
+    #[test]
+    fn glicko_matches_the_paper() {
+        // The player and first opponent from the example in Glickman's
+        // description of Glicko-2, with only the one game in the period
+        let player = Glicko { rating: 1500.0, deviation: 200.0, volatility: 0.06 };
+        let opponent = Glicko { rating: 1400.0, deviation: 30.0, volatility: 0.06 };
+
+        let won = player.updated(&opponent, 1.0);
+
+        assert!((won.rating - 1563.6).abs() < 0.1, "{won:?}");
+        assert!(won.deviation < player.deviation);
+        assert!((won.volatility - 0.06).abs() < 0.001);
+    }
+
+    #[test]
+    fn games_move_ratings_and_are_saved() {
+        let path = std::env::temp_dir().join(format!("ratings-{}.json", std::process::id()));
+        fs::remove_file(&path).ok();
+
+        Ratings::record_in(&path, "engine:8", "human", GameResult::Win(Piece::O)).unwrap();
+        Ratings::record_in(&path, "human", "engine:8", GameResult::Draw).unwrap();
+        Ratings::record_in(&path, "human", "engine:8", GameResult::Ongoing).unwrap();
+        let ratings = Ratings::load(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        let engine = ratings.players["engine:8"];
+        let human = ratings.players["human"];
+        assert_eq!((engine.wins, engine.draws, engine.losses), (1, 1, 0));
+        assert!(engine.elo > INITIAL_RATING && human.elo < INITIAL_RATING);
+        assert!((engine.elo + human.elo - 2.0 * INITIAL_RATING).abs() < 1e-9);
+        assert!(engine.glicko.rating > human.glicko.rating);
+        assert!(ratings.leaderboard().lines().nth(1).unwrap().contains("engine:8"));
+    }
+
+    #[test]
+    fn remote_players_are_rated_by_host() {
+        let remote = PlayerRecord::Remote { address: "10.0.0.2:53412".to_string() };
+
+        assert_eq!(player_name(&remote, "human"), "remote:10.0.0.2");
+        assert_eq!(player_name(&PlayerRecord::Human, "ada"), "ada");
+        assert_eq!(player_name(&PlayerRecord::Engine { depth: 4 }, "ada"), "engine:4");
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{self, Board, Piece};
+
+/// The newest record format this build can read and the one it writes
+pub const VERSION: u32 = 1;
+
+/// Everything needed to resume, share or archive a game.
+///
+/// Records can be written as JSON with serde, or in a compact one-line text
+/// form through [`fmt::Display`] and [`FromStr`], for example
+/// `v1;x=human;o=engine:10;first=o;result=x;started=1700000000;finished=1700000321;moves=4453`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub version: u32,
+    pub x: PlayerRecord,
+    pub o: PlayerRecord,
+    /// The piece that made the first move, always O for now
+    pub first: Piece,
+    /// Columns from 0 to 6 in the order they were played
+    pub moves: Vec<usize>,
+    pub result: GameResult,
+    /// Seconds since the Unix epoch
+    pub started: u64,
+    pub finished: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlayerRecord {
+    Human,
+    Engine { depth: u32 },
+    /// The engine with its own eval weights, see [`crate::engine::EVAL_WEIGHTS`]
+    Tuned { depth: u32, weights: [isize; 4] },
+    /// Monte Carlo tree search with `playouts` random games a move
+    Mcts { playouts: u32 },
+    /// Someone playing over the network from `address`
+    Remote { address: String },
+    /// An engine program, with its arguments separated by spaces and
+    /// quoted if they have spaces in them
+    External { command: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameResult {
+    Ongoing,
+    Draw,
+    Win(Piece),
+    /// The given piece resigned
+    Resigned(Piece),
+    /// The given piece ran out of time
+    TimeForfeit(Piece),
+}
+
+impl GameRecord {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(s: &str) -> serde_json::Result<GameRecord> {
+        let record: GameRecord = serde_json::from_str(s)?;
+        if record.version > VERSION {
+            return Err(serde::de::Error::custom("Record was written by a newer version"));
+        }
+
+        Ok(record)
+    }
+
+    /// The board after the moves, if they are legal and could have ended
+    /// in the recorded result
+    pub fn board(&self) -> Result<Board, &'static str> {
+        if self.first != Piece::O {
+            return Err("Only games where O moves first are supported");
+        }
+
+        let board = Board::from_moves(&self.moves)?;
+
+        let matches = match (board.is_terminal(), self.result) {
+            (Some(Piece::Empty), result) => result == GameResult::Draw,
+            (Some(winner), result) => result == GameResult::Win(winner),
+            // Only a finished game has a winner on the board
+            (None, result) => !matches!(result, GameResult::Win(_)),
+        };
+        if !matches {
+            return Err("The result does not match the moves");
+        }
+
+        Ok(board)
+    }
+}
+
+fn piece_name(piece: Piece) -> &'static str {
+    match piece {
+        Piece::X => "x",
+        Piece::O => "o",
+        Piece::Empty => "-",
+    }
+}
+
+fn parse_piece(s: &str) -> Result<Piece, &'static str> {
+    match s {
+        "x" => Ok(Piece::X),
+        "o" => Ok(Piece::O),
+        _ => Err("Piece must be x or o"),
+    }
+}
+
+impl fmt::Display for PlayerRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlayerRecord::Human => write!(f, "human"),
+            PlayerRecord::Engine { depth } => write!(f, "engine:{depth}"),
+            PlayerRecord::Tuned { depth, weights: [a, b, c, d] } => write!(f, "tuned:{depth}:{a},{b},{c},{d}"),
+            PlayerRecord::Mcts { playouts } => write!(f, "mcts:{playouts}"),
+            PlayerRecord::Remote { address } => write!(f, "remote:{address}"),
+            PlayerRecord::External { command } => write!(f, "external:{command}"),
+        }
+    }
+}
+
+impl FromStr for PlayerRecord {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "human" => Ok(PlayerRecord::Human),
+            Some(("engine", depth)) => Ok(PlayerRecord::Engine {
+                depth: depth.parse().map_err(|_| "Engine depth must be a number")?,
+            }),
+            Some(("tuned", tuned)) => {
+                let bad = "Tuned players must be tuned:<depth>:<weight>,<weight>,<weight>,<weight>";
+                let (depth, weights) = tuned.split_once(':').ok_or(bad)?;
+                let weights: Vec<isize> = weights.split(',').map(|w| w.trim().parse()).collect::<Result<_, _>>().map_err(|_| bad)?;
+                Ok(PlayerRecord::Tuned {
+                    depth: depth.parse().map_err(|_| bad)?,
+                    weights: weights.try_into().map_err(|_| bad)?,
+                })
+            }
+            Some(("mcts", playouts)) => Ok(PlayerRecord::Mcts {
+                playouts: playouts.parse().map_err(|_| "Playouts must be a number")?,
+            }),
+            Some(("remote", address)) => Ok(PlayerRecord::Remote {
+                address: address.to_string(),
+            }),
+            Some(("external", command)) => Ok(PlayerRecord::External {
+                command: command.to_string(),
+            }),
+            _ => Err("Player must be human, engine:<depth>, tuned:<depth>:<weights>, mcts:<playouts>, remote:<address> or external:<command>"),
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResult::Ongoing => write!(f, "*"),
+            GameResult::Draw => write!(f, "draw"),
+            GameResult::Win(piece) => write!(f, "{}", piece_name(*piece)),
+            GameResult::Resigned(piece) => write!(f, "{}-resigned", piece_name(*piece)),
+            GameResult::TimeForfeit(piece) => write!(f, "{}-time", piece_name(*piece)),
+        }
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "*" => Ok(GameResult::Ongoing),
+            "draw" => Ok(GameResult::Draw),
+            _ => match (s.strip_suffix("-resigned"), s.strip_suffix("-time")) {
+                (Some(piece), _) => Ok(GameResult::Resigned(parse_piece(piece)?)),
+                (_, Some(piece)) => Ok(GameResult::TimeForfeit(parse_piece(piece)?)),
+                _ => Ok(GameResult::Win(parse_piece(s)?)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "v{};x={};o={};first={};result={};started={}",
+            self.version,
+            self.x,
+            self.o,
+            piece_name(self.first),
+            self.result,
+            self.started
+        )?;
+        if let Some(finished) = self.finished {
+            write!(f, ";finished={finished}")?;
+        }
+        write!(f, ";moves={}", board::format_moves(&self.moves))
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.trim().split(';');
+
+        let version = fields
+            .next()
+            .and_then(|v| v.strip_prefix('v'))
+            .and_then(|v| v.parse().ok())
+            .ok_or("Record must start with a version like v1")?;
+        if version > VERSION {
+            return Err("Record was written by a newer version");
+        }
+
+        let (mut x, mut o, mut first, mut result, mut started) = (None, None, None, None, None);
+        let mut finished = None;
+        let mut moves = None;
+
+        for field in fields {
+            let (key, value) = field.split_once('=').ok_or("Fields must look like key=value")?;
+            match key {
+                "x" => x = Some(value.parse()?),
+                "o" => o = Some(value.parse()?),
+                "first" => first = Some(parse_piece(value)?),
+                "result" => result = Some(value.parse()?),
+                "started" => started = Some(value.parse().map_err(|_| "Start time must be a number")?),
+                "finished" => finished = Some(value.parse().map_err(|_| "Finish time must be a number")?),
+                "moves" => moves = Some(board::parse_moves(value)?),
+                // Unknown fields are skipped so older builds can read newer additions
+                _ => {}
+            }
+        }
+
+        Ok(GameRecord {
+            version,
+            x: x.ok_or("Record is missing x")?,
+            o: o.ok_or("Record is missing o")?,
+            first: first.ok_or("Record is missing first")?,
+            moves: moves.ok_or("Record is missing moves")?,
+            result: result.ok_or("Record is missing result")?,
+            started: started.ok_or("Record is missing started")?,
+            finished,
+        })
+    }
+}
+
+#[cfg(test)]
+mod record_tests {
+    use super::*;
+
+    fn record() -> GameRecord {
+        GameRecord {
+            version: VERSION,
+            x: PlayerRecord::Human,
+            o: PlayerRecord::Engine { depth: 10 },
+            first: Piece::O,
+            moves: vec![3, 3, 4, 2],
+            result: GameResult::Resigned(Piece::X),
+            started: 1_700_000_000,
+            finished: Some(1_700_000_321),
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let text = record().to_string();
+
+        assert_eq!(
+            text,
+            "v1;x=human;o=engine:10;first=o;result=x-resigned;started=1700000000;finished=1700000321;moves=4453"
+        );
+        assert_eq!(text.parse::<GameRecord>(), Ok(record()));
+    }
+
+    #[test]
+    fn players_round_trip() {
+        for text in ["human", "engine:6", "tuned:6:1,20,300,4000", "mcts:1000", "external:./solver --fast"] {
+            assert_eq!(text.parse::<PlayerRecord>().unwrap().to_string(), text);
+        }
+
+        assert!("tuned:6:1,20,300".parse::<PlayerRecord>().is_err());
+        assert!("mcts:lots".parse::<PlayerRecord>().is_err());
+    }
+
+    #[test]
+    fn results_must_match_the_board() {
+        let mut record = record();
+        assert!(record.board().is_ok());
+
+        record.result = GameResult::Win(Piece::O);
+        assert_eq!(record.board().unwrap_err(), "The result does not match the moves");
+
+        record.moves = vec![0, 1, 0, 1, 0, 1, 0];
+        assert!(record.board().is_ok());
+        record.result = GameResult::Win(Piece::X);
+        assert!(record.board().is_err());
+
+        record.result = GameResult::Win(Piece::O);
+        record.first = Piece::X;
+        assert_eq!(record.board().unwrap_err(), "Only games where O moves first are supported");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = record().to_json().unwrap();
+
+        assert_eq!(GameRecord::from_json(&json).unwrap(), record());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::board::{Board, Piece};
+use crate::theme::{self, Mark, Theme};
+
+const ROWS: usize = 6;
+const COLS: usize = 7;
+
+/// Turns a board into text. Renderers never touch the terminal themselves,
+/// so their output can go to a screen, a log or a test. They are `Send` so
+/// a [`crate::Game`] can move between threads, like the room server's do.
+pub trait Renderer: Send {
+    fn render(&self, board: &Board) -> String;
+
+    /// Whether the screen should be cleared before printing the board
+    fn clear_screen(&self) -> bool {
+        false
+    }
+}
+
+/// Plain text with no colour, which is also what `Display` for [`Board`] uses
+pub struct Ascii;
+
+/// Coloured pieces using ANSI escape codes in the colours of a [`Theme`]
+pub struct Ansi {
+    pub theme: Theme,
+}
+
+/// Round discs inside a box drawn with Unicode line characters. The last
+/// move is marked with an arrow in the top edge and the winning four are
+/// drawn as ringed discs.
+pub struct Unicode;
+
+/// A single line listing the rows from top to bottom, with runs of empty
+/// cells written as numbers, like `7/7/7/7/3o3/2xo3 x`. The last field is
+/// the piece to move.
+pub struct Compact;
+
+/// The cells of a board that should be highlighted: the last piece played
+/// and the four in a row that won the game
+pub struct Marks {
+    last: Option<(usize, usize)>,
+    winning: Option<[(usize, usize); 4]>,
+}
+
+impl Marks {
+    pub fn of(board: &Board) -> Marks {
+        Marks {
+            last: board.last_cell(),
+            winning: board.winning_line(),
+        }
+    }
+
+    pub fn at(&self, col: usize, row: usize) -> Mark {
+        if self.winning.is_some_and(|line| line.contains(&(col, row))) {
+            Mark::Winning
+        } else if self.last == Some((col, row)) {
+            Mark::LastMove
+        } else {
+            Mark::None
+        }
+    }
+}
+
+/// Renders one row per line from the top, with the column numbers on top
+fn grid(board: &Board, cell: impl Fn(Piece, Mark) -> String) -> String {
+    let mut out = String::new();
+    let marks = Marks::of(board);
+
+    let header: Vec<String> = (1..=COLS).map(|x| x.to_string()).collect();
+    out.push_str(&header.join(" "));
+    out.push('\n');
+
+    for y in (0..ROWS).rev() {
+        let row: Vec<String> = (0..COLS).map(|x| cell(board.get(x, y), marks.at(x, y))).collect();
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+
+    out
+}
+
+impl Renderer for Ascii {
+    fn render(&self, board: &Board) -> String {
+        grid(board, |piece, _| {
+            match piece {
+                Piece::X => "X",
+                Piece::O => "O",
+                Piece::Empty => ".",
+            }
+            .to_string()
+        })
+    }
+}
+
+impl Default for Ansi {
+    fn default() -> Self {
+        Ansi {
+            theme: Theme::detect(),
+        }
+    }
+}
+
+impl Renderer for Ansi {
+    fn render(&self, board: &Board) -> String {
+        grid(board, |piece, mark| self.theme.paint_marked(piece, mark))
+    }
+
+    fn clear_screen(&self) -> bool {
+        true
+    }
+}
+
+impl Renderer for Unicode {
+    fn render(&self, board: &Board) -> String {
+        let mut out = String::new();
+
+        let header: Vec<String> = (1..=COLS).map(|x| x.to_string()).collect();
+        out.push_str(&format!("  {}\n", header.join(" ")));
+        let marks = Marks::of(board);
+
+        let mut top: Vec<char> = "─".repeat(COLS * 2 + 1).chars().collect();
+        if let Some((col, _)) = board.last_cell() {
+            top[col * 2 + 1] = '▼';
+        }
+        out.push_str(&format!("┌{}┐\n", top.iter().collect::<String>()));
+
+        for y in (0..ROWS).rev() {
+            let row: Vec<&str> = (0..COLS)
+                .map(|x| match (board.get(x, y), marks.at(x, y)) {
+                    (Piece::X, Mark::Winning) => "◉",
+                    (Piece::O, Mark::Winning) => "◎",
+                    (Piece::X, _) => "●",
+                    (Piece::O, _) => "○",
+                    (Piece::Empty, _) => "·",
+                })
+                .collect();
+            out.push_str(&format!("│ {} │\n", row.join(" ")));
+        }
+
+        out.push_str(&format!("└{}┘\n", "─".repeat(COLS * 2 + 1)));
+
+        out
+    }
+}
+
+impl Renderer for Compact {
+    fn render(&self, board: &Board) -> String {
+        let mut rows = Vec::new();
+
+        for y in (0..ROWS).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+
+            for x in 0..COLS {
+                let piece = board.get(x, y);
+                if piece == Piece::Empty {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    row.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                row.push(if piece == Piece::X { 'x' } else { 'o' });
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+
+            rows.push(row);
+        }
+
+        let to_move = if board.to_move() == Piece::X { 'x' } else { 'o' };
+
+        format!("{} {to_move}", rows.join("/"))
+    }
+}
+
+/// Coloured output on a terminal, and plain text when stdout is piped to a
+/// file or another program so no escape codes end up there
+pub fn detect() -> Box<dyn Renderer> {
+    if theme::is_terminal() {
+        Box::new(Ansi::default())
+    } else {
+        Box::new(Ascii)
+    }
+}
+
+/// Looks up a renderer by the name used on the command line
+pub fn by_name(name: &str) -> Option<Box<dyn Renderer>> {
+    match name {
+        "ascii" => Some(Box::new(Ascii)),
+        "ansi" => Some(Box::new(Ansi::default())),
+        "unicode" => Some(Box::new(Unicode)),
+        "compact" => Some(Box::new(Compact)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::*;
+
+    #[test]
+    fn ascii_has_no_escape_codes() {
+        let board = Board::from_moves(&[3, 2]).unwrap();
+
+        let text = Ascii.render(&board);
+
+        assert!(!text.contains('\x1b'));
+        assert!(text.ends_with(". . X O . . .\n"));
+        assert_eq!(text, board.to_string());
+    }
+
+    #[test]
+    fn unicode_marks_last_move_and_winning_line() {
+        let board = Board::from_moves(&[0, 6, 0, 6, 0, 6, 0]).unwrap();
+
+        let text = Unicode.render(&board);
+
+        assert!(text.contains("┌─▼─"));
+        assert_eq!(text.matches('◎').count(), 4);
+    }
+
+    #[test]
+    fn compact_writes_runs_of_empty_cells() {
+        let board = Board::from_moves(&[3, 2, 3]).unwrap();
+
+        assert_eq!(Compact.render(&board), "7/7/7/7/3o3/2xo3 x");
+    }
+}
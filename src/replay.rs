@@ -0,0 +1,195 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::board::Board;
+use crate::export::{self, ImageOptions};
+use crate::raster::{Canvas, Shape};
+
+/// How a finished game is turned into an animation
+#[derive(Clone, Debug)]
+pub struct ReplayOptions {
+    /// Options for every frame. Move numbers are filled in from the moves
+    /// being replayed when `image.moves` is set.
+    pub image: ImageOptions,
+    /// How long each position is shown after its disc has landed
+    pub frame_delay: Duration,
+    /// How long the final position is shown before the animation loops
+    pub final_delay: Duration,
+    /// In-between frames for each falling disc, 0 to skip the animation
+    pub drop_frames: u32,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions {
+            image: ImageOptions {
+                last_move: false,
+                ..ImageOptions::default()
+            },
+            frame_delay: Duration::from_millis(600),
+            final_delay: Duration::from_secs(3),
+            drop_frames: 4,
+        }
+    }
+}
+
+/// A rendered image and how long it stays on screen
+pub struct Frame {
+    pub canvas: Canvas,
+    pub delay: Duration,
+}
+
+/// Delay between the in-between frames of a falling disc
+const DROP_DELAY: Duration = Duration::from_millis(40);
+
+/// Renders the empty board, every disc falling into place and the final
+/// position, where the winning line is drawn when `image.winning_line` is set
+pub fn frames(moves: &[usize], options: &ReplayOptions) -> Result<Vec<Frame>, &'static str> {
+    let size = options.image.cell_size as f32;
+    let (width, height) = export::image_size(&options.image)?;
+
+    // Only the final frame shows the winning line
+    let mut image = options.image.clone();
+    image.winning_line = false;
+
+    let mut board = Board::new();
+    let mut frames = vec![Frame {
+        canvas: export::to_canvas(&board, &image)?,
+        delay: options.frame_delay,
+    }];
+
+    for (i, &col) in moves.iter().enumerate() {
+        if image.moves.is_some() {
+            image.moves = Some(moves[..i].to_vec());
+        }
+
+        if board.is_terminal().is_some() {
+            return Err("Game is already over");
+        }
+        if col >= 7 || board.column_height(col) >= 6 {
+            return Err("Column does not exist or is full");
+        }
+
+        let piece = board.to_move();
+        let landing = export::cell_center((col, board.column_height(col)), size);
+        let top = export::cell_center((col, 5), size);
+        let before = export::shapes(&board, &image);
+
+        // The disc speeds up as it falls, like it does under gravity
+        for frame in 0..options.drop_frames {
+            let t = frame as f32 / options.drop_frames as f32;
+            let cy = top.1 + (landing.1 - top.1) * t * t;
+            let mut shapes = before.clone();
+            shapes.push(Shape::Circle { cx: landing.0, cy, r: size * 0.4, color: export::piece_color(piece) });
+
+            let mut canvas = Canvas::new(width, height)?;
+            canvas.draw(&shapes);
+            frames.push(Frame { canvas, delay: DROP_DELAY });
+        }
+
+        board.insert_piece(col, piece)?;
+
+        if image.moves.is_some() {
+            image.moves = Some(moves[..=i].to_vec());
+        }
+        let last = i == moves.len() - 1;
+        if last {
+            image.winning_line = options.image.winning_line;
+        }
+
+        frames.push(Frame {
+            canvas: export::to_canvas(&board, &image)?,
+            delay: if last { options.final_delay } else { options.frame_delay },
+        });
+    }
+
+    Ok(frames)
+}
+
+pub fn to_gif(moves: &[usize], options: &ReplayOptions) -> io::Result<Vec<u8>> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidInput, e);
+
+    // Checked before drawing anything, so a bad size fails straight away
+    let (width, height) = export::image_size(&options.image).map_err(invalid)?;
+    let (width, height) = (
+        u16::try_from(width).map_err(|_| invalid("Image is too wide for a GIF"))?,
+        u16::try_from(height).map_err(|_| invalid("Image is too tall for a GIF"))?,
+    );
+    let frames = frames(moves, options).map_err(invalid)?;
+
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut bytes, width, height, &[]).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+
+        for frame in frames {
+            let mut gif_frame = gif::Frame::from_rgb_speed(width, height, &frame.canvas.pixels, 10);
+            // GIF delays are in hundredths of a second
+            gif_frame.delay = (frame.delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+            encoder.write_frame(&gif_frame).map_err(io::Error::other)?;
+        }
+    }
+
+    Ok(bytes)
+}
+
+pub fn save(moves: &[usize], options: &ReplayOptions, path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, to_gif(moves, options)?)
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use super::*;
+
+    #[test]
+    fn one_frame_per_drop_step_and_position() {
+        let options = ReplayOptions {
+            image: ImageOptions {
+                cell_size: 8,
+                ..ImageOptions::default()
+            },
+            ..ReplayOptions::default()
+        };
+
+        let frames = frames(&[3, 3, 2], &options).unwrap();
+
+        assert_eq!(frames.len(), 1 + 3 * (options.drop_frames as usize + 1));
+        assert_eq!(frames.last().unwrap().delay, options.final_delay);
+    }
+
+    #[test]
+    fn gif_is_encoded() {
+        let options = ReplayOptions {
+            image: ImageOptions {
+                cell_size: 8,
+                ..ImageOptions::default()
+            },
+            drop_frames: 0,
+            ..ReplayOptions::default()
+        };
+
+        let gif = to_gif(&[0, 1, 0, 1, 0, 1, 0], &options).unwrap();
+
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert!(to_gif(&[0, 1, 0, 1, 0, 1, 0, 1], &options).is_err());
+    }
+
+    #[test]
+    fn size_is_checked_before_drawing() {
+        let options = ReplayOptions {
+            image: ImageOptions {
+                cell_size: u32::MAX,
+                ..ImageOptions::default()
+            },
+            ..ReplayOptions::default()
+        };
+
+        // The size is reported before the seventh move into a full column is
+        // reached, so no frame is drawn
+        let error = to_gif(&[3; 42], &options).unwrap_err();
+
+        assert_eq!(error.to_string(), "Cells must be from 1 to 512 pixels");
+    }
+}
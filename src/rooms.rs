@@ -0,0 +1,673 @@
+//! A WebSocket server hosting many games at once. Each room has two seats,
+//! taken by people or the engine, and any number of spectators who are sent
+//! the board after every move. Players who lose their connection can take
+//! their seat back with the token they got when they sat down, as long as
+//! the room has not been empty for [`ABANDONED_AFTER`].
+//!
+//! Each room plays a [`Game`], so moves, results and resignations follow
+//! the same rules as games played any other way.
+//!
+//! Messages are JSON objects with a `type` field, see [`ClientMessage`] and
+//! [`ServerMessage`].
+
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tungstenite::handshake::HandshakeRole;
+use tungstenite::{HandshakeError, Message, WebSocket};
+
+use crate::board::{self, Board, Piece};
+use crate::engine::Engine;
+use crate::human::Human;
+use crate::record::GameResult;
+use crate::{Action, Game, Player};
+
+/// How often connections look for messages to send while waiting for input
+const POLL: Duration = Duration::from_millis(50);
+
+/// The deepest an engine seat searches, the same as the HTTP API allows
+pub const MAX_ENGINE_DEPTH: u32 = 12;
+
+/// The longest an engine seat thinks about a move
+const ENGINE_MOVETIME: Duration = Duration::from_secs(2);
+
+/// How long a room with a game in progress is kept once everyone has left
+pub const ABANDONED_AFTER: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Opens a new room and takes the O seat, with the engine searching to
+    /// `engine` depth in the X seat if it is given. The depth goes up to
+    /// [`MAX_ENGINE_DEPTH`].
+    Create { engine: Option<u32> },
+    /// Takes the free seat in a room
+    Join { room: String },
+    Watch { room: String },
+    /// Takes back a seat after losing the connection
+    Rejoin { room: String, token: String },
+    /// A column from 1 to 7
+    Move { column: usize },
+    /// Resigns the game, on your turn like in every other game
+    Resign,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Sent after create, join, watch and rejoin. Spectators have no seat
+    /// and no token.
+    Joined {
+        room: String,
+        seat: Option<Piece>,
+        token: Option<String>,
+    },
+    /// Sent to everyone in a room whenever something changes
+    State(RoomState),
+    Error { message: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoomState {
+    pub room: String,
+    /// Columns from 1 to 7, like `4453`
+    pub moves: String,
+    pub to_move: Piece,
+    pub result: GameResult,
+    pub o: SeatState,
+    pub x: SeatState,
+    pub spectators: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeatState {
+    Open,
+    Connected,
+    /// Held for a player who lost their connection
+    Disconnected,
+    Engine,
+}
+
+/// Where messages for one connection go
+#[derive(Clone)]
+struct Client {
+    id: u64,
+    sender: Sender<ServerMessage>,
+}
+
+impl Client {
+    fn send(&self, message: ServerMessage) {
+        // A closed channel means the connection is on its way out
+        self.sender.send(message).ok();
+    }
+}
+
+/// Who sits in a seat. The engine's settings are kept by the room's game.
+enum Seat {
+    Open,
+    Human { token: String, client: Option<Client> },
+    Engine,
+}
+
+impl Seat {
+    fn state(&self) -> SeatState {
+        match self {
+            Seat::Open => SeatState::Open,
+            Seat::Human { client: Some(_), .. } => SeatState::Connected,
+            Seat::Human { client: None, .. } => SeatState::Disconnected,
+            Seat::Engine => SeatState::Engine,
+        }
+    }
+
+    fn is(&self, id: u64) -> bool {
+        matches!(self, Seat::Human { client: Some(client), .. } if client.id == id)
+    }
+}
+
+struct Room {
+    id: String,
+    game: Game,
+    o: Seat,
+    x: Seat,
+    spectators: Vec<Client>,
+    /// When the last person connected to the room left
+    empty_since: Option<Instant>,
+}
+
+impl Room {
+    /// A room with the engine in the X seat if it is given
+    fn new(id: String, engine: Option<Engine>) -> Room {
+        let (x, game) = match engine {
+            Some(engine) => (Seat::Engine, Game::with_players(Player::Engine(engine), Player::Human(Human))),
+            None => (Seat::Open, Game::with_players(Player::Human(Human), Player::Human(Human))),
+        };
+
+        Room {
+            id,
+            game,
+            o: Seat::Open,
+            x,
+            spectators: Vec::new(),
+            empty_since: None,
+        }
+    }
+
+    fn seat(&mut self, piece: Piece) -> &mut Seat {
+        if piece == Piece::O { &mut self.o } else { &mut self.x }
+    }
+
+    /// The piece of the seat `id` sits in
+    fn piece_of(&self, id: u64) -> Option<Piece> {
+        if self.o.is(id) {
+            Some(Piece::O)
+        } else if self.x.is(id) {
+            Some(Piece::X)
+        } else {
+            None
+        }
+    }
+
+    fn state(&self) -> RoomState {
+        RoomState {
+            room: self.id.clone(),
+            moves: board::format_moves(&self.game.moves),
+            to_move: self.game.board.to_move(),
+            result: self.game.result(),
+            o: self.o.state(),
+            x: self.x.state(),
+            spectators: self.spectators.len(),
+        }
+    }
+
+    fn clients(&self) -> impl Iterator<Item = &Client> {
+        let seated = [&self.o, &self.x].into_iter().filter_map(|seat| match seat {
+            Seat::Human { client: Some(client), .. } => Some(client),
+            _ => None,
+        });
+
+        seated.chain(&self.spectators)
+    }
+
+    fn broadcast(&self) {
+        let state = self.state();
+        for client in self.clients() {
+            client.send(ServerMessage::State(state.clone()));
+        }
+    }
+
+    /// Carries out a move or resignation for the piece to move
+    fn act(&mut self, action: Action) -> Result<(), &'static str> {
+        if self.game.result() != GameResult::Ongoing {
+            return Err("The game is over");
+        }
+        if let Action::Move(col) = action
+            && !self.game.board.get_moves().contains(&col)
+        {
+            return Err("That column is full");
+        }
+
+        // The game's players are local humans and the engine, which it never
+        // loses the connection to, so this always carries on
+        self.game.apply(action);
+        Ok(())
+    }
+
+    /// The engine and position to search if it is the engine's turn
+    fn engine_turn(&mut self) -> Option<(Engine, Board)> {
+        if self.game.result() != GameResult::Ongoing {
+            return None;
+        }
+
+        match self.game.player(self.game.board.to_move()) {
+            Player::Engine(engine) => Some((engine.clone(), self.game.board.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Every room on the server, and the room each connection is in
+#[derive(Default)]
+struct Lobby {
+    rooms: HashMap<String, Room>,
+    clients: HashMap<u64, String>,
+    next_room: u64,
+}
+
+/// A hard to guess string for taking a seat back
+fn new_token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    hasher.write_u128(nanos);
+    format!("{:016x}", hasher.finish())
+}
+
+impl Lobby {
+    /// Carries out a message from `client`, returning the room where the
+    /// engine is to move next, if any
+    fn handle(&mut self, client: &Client, message: ClientMessage) -> Result<Option<String>, &'static str> {
+        self.close_abandoned();
+
+        let room_id = match message {
+            ClientMessage::Create { engine } => {
+                if engine.is_some_and(|depth| !(1..=MAX_ENGINE_DEPTH).contains(&depth)) {
+                    return Err("Engine depth must be from 1 to 12");
+                }
+                self.leave(client.id);
+                self.next_room += 1;
+                let id = self.next_room.to_string();
+
+                let engine = engine.map(|depth| Engine { depth, movetime: Some(ENGINE_MOVETIME), ..Engine::default() });
+                self.rooms.insert(id.clone(), Room::new(id.clone(), engine));
+                self.sit(client, &id, Piece::O, new_token());
+                id
+            }
+            ClientMessage::Join { room } => {
+                let seat = {
+                    let found = self.rooms.get(&room).ok_or("There is no such room")?;
+                    [Piece::O, Piece::X]
+                        .into_iter()
+                        .find(|&piece| matches!(if piece == Piece::O { &found.o } else { &found.x }, Seat::Open))
+                        .ok_or("The room is full, watch it instead")?
+                };
+                self.leave(client.id);
+                self.sit(client, &room, seat, new_token());
+                room
+            }
+            ClientMessage::Watch { room } => {
+                if !self.rooms.contains_key(&room) {
+                    return Err("There is no such room");
+                }
+                self.leave(client.id);
+
+                let found = self.rooms.get_mut(&room).expect("room was checked");
+                found.spectators.push(client.clone());
+                self.clients.insert(client.id, room.clone());
+                client.send(ServerMessage::Joined { room: room.clone(), seat: None, token: None });
+                room
+            }
+            ClientMessage::Rejoin { room, token } => {
+                let found = self.rooms.get(&room).ok_or("There is no such room")?;
+                let seat = [(Piece::O, &found.o), (Piece::X, &found.x)]
+                    .into_iter()
+                    .find(|(_, seat)| matches!(seat, Seat::Human { token: held, .. } if *held == token))
+                    .map(|(piece, _)| piece)
+                    .ok_or("That token does not hold a seat in the room")?;
+
+                self.leave(client.id);
+                self.sit(client, &room, seat, token);
+                room
+            }
+            ClientMessage::Move { column } => {
+                let room = self.on_turn(client.id)?;
+                if !(1..=7).contains(&column) {
+                    return Err("Columns go from 1 to 7");
+                }
+                room.act(Action::Move(column - 1))?;
+                room.id.clone()
+            }
+            ClientMessage::Resign => {
+                let room = self.on_turn(client.id)?;
+                room.act(Action::Resign)?;
+                room.id.clone()
+            }
+        };
+
+        let room = self.rooms.get_mut(&room_id).expect("room exists");
+        room.empty_since = None;
+        room.broadcast();
+
+        Ok(room.engine_turn().map(|_| room_id))
+    }
+
+    fn sit(&mut self, client: &Client, room_id: &str, piece: Piece, token: String) {
+        let room = self.rooms.get_mut(room_id).expect("room exists");
+        *room.seat(piece) = Seat::Human { token: token.clone(), client: Some(client.clone()) };
+        self.clients.insert(client.id, room_id.to_string());
+
+        client.send(ServerMessage::Joined {
+            room: room_id.to_string(),
+            seat: Some(piece),
+            token: Some(token),
+        });
+    }
+
+    /// The room `id` has a seat in, if it is their turn there
+    fn on_turn(&mut self, id: u64) -> Result<&mut Room, &'static str> {
+        let room = self.clients.get(&id).and_then(|room| self.rooms.get_mut(room));
+        let room = room.ok_or("You are not in a room")?;
+        let piece = room.piece_of(id).ok_or("Spectators cannot play")?;
+        if room.game.board.to_move() != piece {
+            return Err("It is not your turn");
+        }
+
+        Ok(room)
+    }
+
+    /// Takes `id` out of their room. Seats are held so the player can
+    /// rejoin, and rooms are closed once a finished game has nobody left,
+    /// or a game in progress has had nobody for [`ABANDONED_AFTER`].
+    fn leave(&mut self, id: u64) {
+        let Some(room_id) = self.clients.remove(&id) else {
+            return;
+        };
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+
+        room.spectators.retain(|client| client.id != id);
+        for seat in [&mut room.o, &mut room.x] {
+            if seat.is(id)
+                && let Seat::Human { client, .. } = seat
+            {
+                *client = None;
+            }
+        }
+
+        if room.clients().next().is_none() && room.game.result() != GameResult::Ongoing {
+            self.rooms.remove(&room_id);
+        } else {
+            if room.clients().next().is_none() {
+                room.empty_since = Some(Instant::now());
+            }
+            room.broadcast();
+        }
+
+        self.close_abandoned();
+    }
+
+    /// Closes the rooms nobody has been back to in time, so games people
+    /// walk away from do not pile up
+    fn close_abandoned(&mut self) {
+        self.rooms
+            .retain(|_, room| room.empty_since.is_none_or(|since| since.elapsed() < ABANDONED_AFTER));
+    }
+}
+
+/// Searches the engine's move outside the lock, so other rooms carry on
+fn play_engine(lobby: &Mutex<Lobby>, room_id: &str) {
+    let turn = lobby.lock().expect("lobby lock poisoned").rooms.get_mut(room_id).and_then(Room::engine_turn);
+    let Some((engine, board)) = turn else {
+        return;
+    };
+
+    let (col, _) = engine.best_move(&board);
+
+    let mut lobby = lobby.lock().expect("lobby lock poisoned");
+    if let Some(room) = lobby.rooms.get_mut(room_id)
+        && room.game.moves.len() == board.move_count()
+        && room.act(Action::Move(col)).is_ok()
+    {
+        room.broadcast();
+    }
+}
+
+fn would_block(e: &tungstenite::Error) -> bool {
+    matches!(e, tungstenite::Error::Io(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut))
+}
+
+fn handshake_error<S: HandshakeRole>(e: HandshakeError<S>) -> tungstenite::Error {
+    match e {
+        HandshakeError::Failure(e) => e,
+        HandshakeError::Interrupted(_) => tungstenite::Error::Io(io::ErrorKind::WouldBlock.into()),
+    }
+}
+
+fn connection(lobby: Arc<Mutex<Lobby>>, stream: TcpStream, id: u64) -> tungstenite::Result<()> {
+    let mut socket = tungstenite::accept(stream).map_err(handshake_error)?;
+    socket.get_ref().set_read_timeout(Some(POLL))?;
+
+    let (sender, outgoing) = mpsc::channel();
+    let client = Client { id, sender };
+
+    let result = loop {
+        let mut sent = Ok(());
+        while let Ok(message) = outgoing.try_recv() {
+            let json = serde_json::to_string(&message).expect("messages serialize");
+            sent = socket.send(Message::text(json));
+            if sent.is_err() {
+                break;
+            }
+        }
+        if let Err(e) = sent {
+            break Err(e);
+        }
+
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => break Ok(()),
+            Ok(_) => continue,
+            Err(e) if would_block(&e) => continue,
+            Err(tungstenite::Error::ConnectionClosed) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+
+        let handled = match serde_json::from_str(text.as_str()) {
+            Ok(message) => lobby.lock().expect("lobby lock poisoned").handle(&client, message),
+            Err(_) => Err("Messages must be JSON with a known type"),
+        };
+        match handled {
+            Ok(Some(room)) => {
+                let lobby = lobby.clone();
+                thread::spawn(move || play_engine(&lobby, &room));
+            }
+            Ok(None) => {}
+            Err(message) => client.send(ServerMessage::Error { message: message.to_string() }),
+        }
+    };
+
+    lobby.lock().expect("lobby lock poisoned").leave(id);
+    result
+}
+
+/// Accepts connections on `listener` until the process ends, each on its
+/// own thread
+pub fn serve(listener: TcpListener) {
+    let lobby = Arc::new(Mutex::new(Lobby::default()));
+
+    for (id, stream) in listener.incoming().enumerate() {
+        let Ok(stream) = stream else { continue };
+        let lobby = lobby.clone();
+        thread::spawn(move || connection(lobby, stream, id as u64));
+    }
+}
+
+/// A connection to a room server, used by the command line client and tests
+pub struct RoomClient {
+    socket: WebSocket<TcpStream>,
+}
+
+impl RoomClient {
+    pub fn connect(address: impl ToSocketAddrs) -> tungstenite::Result<RoomClient> {
+        let stream = TcpStream::connect(address)?;
+        let url = format!("ws://{}/", stream.peer_addr()?);
+        let (socket, _) = tungstenite::client(url, stream).map_err(handshake_error)?;
+        socket.get_ref().set_read_timeout(Some(POLL))?;
+
+        Ok(RoomClient { socket })
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> tungstenite::Result<()> {
+        let json = serde_json::to_string(message).expect("messages serialize");
+        self.socket.send(Message::text(json))
+    }
+
+    /// The next message from the server, or `None` if nothing arrived for
+    /// a short while
+    pub fn poll(&mut self) -> tungstenite::Result<Option<ServerMessage>> {
+        match self.socket.read() {
+            Ok(Message::Text(text)) => serde_json::from_str(text.as_str())
+                .map(Some)
+                .map_err(|e| tungstenite::Error::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Ok(_) => Ok(None),
+            Err(e) if would_block(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Waits for the next message from the server
+    pub fn receive(&mut self) -> tungstenite::Result<ServerMessage> {
+        loop {
+            if let Some(message) = self.poll()? {
+                return Ok(message);
+            }
+        }
+    }
+
+    pub fn close(mut self) {
+        self.socket.close(None).ok();
+        self.socket.flush().ok();
+    }
+}
+
+#[cfg(test)]
+mod rooms_tests {
+    use super::*;
+
+    fn server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener));
+        address
+    }
+
+    /// Skips messages until a state with `moves`
+    fn wait_for_moves(client: &mut RoomClient, moves: &str) -> RoomState {
+        loop {
+            if let ServerMessage::State(state) = client.receive().unwrap()
+                && state.moves == moves
+            {
+                return state;
+            }
+        }
+    }
+
+    fn joined(client: &mut RoomClient) -> (String, Option<String>) {
+        loop {
+            if let ServerMessage::Joined { room, token, .. } = client.receive().unwrap() {
+                return (room, token);
+            }
+        }
+    }
+
+    #[test]
+    fn players_spectators_and_reconnects() {
+        let address = server();
+
+        let mut o = RoomClient::connect(&address).unwrap();
+        o.send(&ClientMessage::Create { engine: None }).unwrap();
+        let (room, _) = joined(&mut o);
+
+        let mut x = RoomClient::connect(&address).unwrap();
+        x.send(&ClientMessage::Join { room: room.clone() }).unwrap();
+        let (_, token) = joined(&mut x);
+        x.send(&ClientMessage::Move { column: 4 }).unwrap();
+        let error = loop {
+            if let ServerMessage::Error { message } = x.receive().unwrap() {
+                break message;
+            }
+        };
+        assert_eq!(error, "It is not your turn");
+
+        let mut spectator = RoomClient::connect(&address).unwrap();
+        spectator.send(&ClientMessage::Watch { room: room.clone() }).unwrap();
+
+        o.send(&ClientMessage::Move { column: 4 }).unwrap();
+        assert_eq!(wait_for_moves(&mut spectator, "4").to_move, Piece::X);
+
+        // X drops out and comes back with the token
+        x.close();
+        let mut x = RoomClient::connect(&address).unwrap();
+        x.send(&ClientMessage::Rejoin { room: room.clone(), token: token.unwrap() }).unwrap();
+        joined(&mut x);
+        x.send(&ClientMessage::Move { column: 3 }).unwrap();
+
+        let state = wait_for_moves(&mut spectator, "43");
+        assert_eq!(state.x, SeatState::Connected);
+        assert_eq!(state.spectators, 1);
+    }
+
+    #[test]
+    fn engine_depth_is_capped() {
+        let (sender, _outgoing) = mpsc::channel();
+        let client = Client { id: 0, sender };
+        let mut lobby = Lobby::default();
+
+        let error = lobby.handle(&client, ClientMessage::Create { engine: Some(42) });
+
+        assert_eq!(error, Err("Engine depth must be from 1 to 12"));
+        assert!(lobby.rooms.is_empty());
+    }
+
+    #[test]
+    fn rooms_play_by_the_game_rules() {
+        let (sender, _outgoing) = mpsc::channel();
+        let o = Client { id: 0, sender };
+        let x = Client { id: 1, ..o.clone() };
+        let mut lobby = Lobby::default();
+        lobby.handle(&o, ClientMessage::Create { engine: None }).unwrap();
+        lobby.handle(&x, ClientMessage::Join { room: "1".to_string() }).unwrap();
+
+        for column in [1, 2, 1, 2, 1, 2] {
+            let client = if lobby.rooms["1"].game.board.to_move() == Piece::O { &o } else { &x };
+            lobby.handle(client, ClientMessage::Move { column }).unwrap();
+        }
+        assert_eq!(lobby.handle(&x, ClientMessage::Resign), Err("It is not your turn"));
+
+        lobby.handle(&o, ClientMessage::Move { column: 1 }).unwrap();
+        let room = &lobby.rooms["1"];
+        assert_eq!(room.game.result(), GameResult::Win(Piece::O));
+        assert_eq!(room.state().moves, "1212121");
+        assert_eq!(lobby.handle(&x, ClientMessage::Resign), Err("The game is over"));
+    }
+
+    #[test]
+    fn abandoned_games_are_closed() {
+        let (sender, _outgoing) = mpsc::channel();
+        let client = Client { id: 0, sender };
+        let mut lobby = Lobby::default();
+        lobby.handle(&client, ClientMessage::Create { engine: None }).unwrap();
+        lobby.handle(&client, ClientMessage::Move { column: 4 }).unwrap();
+
+        // The seat is held for a while after the player leaves
+        lobby.leave(client.id);
+        let room = lobby.rooms.get_mut("1").unwrap();
+        assert!(room.empty_since.is_some());
+
+        room.empty_since = Some(Instant::now() - ABANDONED_AFTER);
+        let other = Client { id: 1, ..client };
+        lobby.handle(&other, ClientMessage::Create { engine: None }).unwrap();
+
+        assert!(!lobby.rooms.contains_key("1"));
+    }
+
+    #[test]
+    fn engine_answers_moves() {
+        let address = server();
+
+        let mut o = RoomClient::connect(&address).unwrap();
+        o.send(&ClientMessage::Create { engine: Some(2) }).unwrap();
+        joined(&mut o);
+
+        o.send(&ClientMessage::Move { column: 4 }).unwrap();
+
+        let state = loop {
+            if let ServerMessage::State(state) = o.receive().unwrap()
+                && state.moves.len() == 2
+            {
+                break state;
+            }
+        };
+
+        assert_eq!(state.x, SeatState::Engine);
+        assert_eq!(state.to_move, Piece::O);
+    }
+}
@@ -0,0 +1,162 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+use crossterm::style::{Color, Stylize};
+
+use crate::board::Piece;
+
+/// How a single kind of cell is drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PieceStyle {
+    pub symbol: char,
+    /// `None` leaves the terminal's own colour alone
+    pub color: Option<Color>,
+    pub bold: bool,
+}
+
+/// Why a cell should stand out from the rest of the board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mark {
+    None,
+    /// The piece that was played last, drawn underlined
+    LastMove,
+    /// Part of the four in a row that ended the game, drawn in reverse video
+    Winning,
+}
+
+/// Symbols and colours for the pieces and the empty cells
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub x: PieceStyle,
+    pub o: PieceStyle,
+    pub empty: PieceStyle,
+}
+
+const fn style(symbol: char, color: Option<Color>, bold: bool) -> PieceStyle {
+    PieceStyle { symbol, color, bold }
+}
+
+impl Theme {
+    /// Red X, yellow O and blue `#`
+    pub const CLASSIC: Theme = Theme {
+        x: style('X', Some(Color::DarkRed), false),
+        o: style('O', Some(Color::DarkYellow), false),
+        empty: style('#', Some(Color::DarkBlue), false),
+    };
+
+    /// Blue and orange from the Okabe-Ito palette, which stay distinct
+    /// with the common kinds of colour blindness
+    pub const COLORBLIND: Theme = Theme {
+        x: style('X', Some(Color::Rgb { r: 0, g: 114, b: 178 }), true),
+        o: style('O', Some(Color::Rgb { r: 230, g: 159, b: 0 }), true),
+        empty: style('.', Some(Color::DarkGrey), false),
+    };
+
+    /// Bright bold pieces on dim empty cells
+    pub const HIGH_CONTRAST: Theme = Theme {
+        x: style('X', Some(Color::White), true),
+        o: style('O', Some(Color::Yellow), true),
+        empty: style('.', Some(Color::DarkGrey), false),
+    };
+
+    /// No colour at all, the pieces are told apart by their symbols
+    pub const MONOCHROME: Theme = Theme {
+        x: style('X', None, false),
+        o: style('O', None, false),
+        empty: style('.', None, false),
+    };
+
+    pub fn by_name(name: &str) -> Option<Theme> {
+        match name {
+            "classic" => Some(Theme::CLASSIC),
+            "colorblind" => Some(Theme::COLORBLIND),
+            "high-contrast" => Some(Theme::HIGH_CONTRAST),
+            "mono" | "monochrome" => Some(Theme::MONOCHROME),
+            _ => None,
+        }
+    }
+
+    /// The classic theme, or monochrome when `NO_COLOR` is set
+    /// (see <https://no-color.org>)
+    pub fn detect() -> Theme {
+        if no_color() {
+            Theme::MONOCHROME
+        } else {
+            Theme::CLASSIC
+        }
+    }
+
+    pub fn style(&self, piece: Piece) -> PieceStyle {
+        match piece {
+            Piece::X => self.x,
+            Piece::O => self.o,
+            Piece::Empty => self.empty,
+        }
+    }
+
+    /// The symbol for `piece` wrapped in the escape codes for its colour
+    pub fn paint(&self, piece: Piece) -> String {
+        self.paint_marked(piece, Mark::None)
+    }
+
+    /// Like [`Theme::paint`], with extra attributes for a marked cell. The
+    /// marks do not rely on colour, so they also show in monochrome.
+    pub fn paint_marked(&self, piece: Piece, mark: Mark) -> String {
+        let style = self.style(piece);
+        let mut content = style.symbol.stylize();
+
+        if let Some(color) = style.color {
+            content = content.with(color);
+        }
+        if style.bold {
+            content = content.bold();
+        }
+        match mark {
+            Mark::None => {}
+            Mark::LastMove => content = content.underlined(),
+            Mark::Winning => content = content.reverse().bold(),
+        }
+
+        content.to_string()
+    }
+}
+
+/// Whether the user asked for no colours with a non-empty `NO_COLOR`
+pub fn no_color() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// Whether stdout is a terminal rather than a pipe or a file
+pub fn is_terminal() -> bool {
+    io::stdout().is_terminal()
+}
+
+#[cfg(test)]
+mod theme_tests {
+    use super::*;
+
+    #[test]
+    fn monochrome_has_no_escape_codes() {
+        for piece in [Piece::X, Piece::O, Piece::Empty] {
+            assert!(!Theme::MONOCHROME.paint(piece).contains('\x1b'));
+        }
+    }
+
+    #[test]
+    fn marks_show_without_colour() {
+        let plain = Theme::MONOCHROME.paint(Piece::X);
+
+        assert_ne!(Theme::MONOCHROME.paint_marked(Piece::X, Mark::LastMove), plain);
+        assert_ne!(Theme::MONOCHROME.paint_marked(Piece::X, Mark::Winning), plain);
+    }
+
+    #[test]
+    fn themes_keep_pieces_apart() {
+        for name in ["classic", "colorblind", "high-contrast", "mono"] {
+            let theme = Theme::by_name(name).unwrap();
+
+            assert_ne!(theme.paint(Piece::X), theme.paint(Piece::O));
+            assert_ne!(theme.x.symbol, theme.o.symbol);
+        }
+    }
+}
@@ -0,0 +1,542 @@
+//! Tournaments between engines of different depths and eval weights, Monte
+//! Carlo tree search and engine programs, played as a round robin or with
+//! Swiss pairings. The whole tournament is saved after every game, so one
+//! that was interrupted carries on where it stopped.
+//!
+//! Every pairing is played as two games from the same opening, one with
+//! each player moving first, since an engine always plays the same game
+//! from the same position.
+//!
+//! A loaded tournament does not run the engine programs named in its file
+//! until [`Tournament::allow_programs`] is called, since files can come from
+//! anyone.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::arena;
+use crate::board::Piece;
+use crate::record::{GameResult, PlayerRecord};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Format {
+    /// Everyone meets everyone `cycles` times
+    RoundRobin { cycles: usize },
+    /// Players with similar scores meet, without rematches where possible.
+    /// There can be at most as many rounds as a round robin has, see
+    /// [`Tournament::new`].
+    Swiss { rounds: usize },
+}
+
+/// One game of the tournament. Players are indices into
+/// [`Tournament::players`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TournamentGame {
+    pub round: usize,
+    pub o: usize,
+    pub x: usize,
+    pub opening: Vec<usize>,
+    /// `None` until the game is played
+    pub result: Option<GameResult>,
+    pub moves: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tournament {
+    pub format: Format,
+    pub players: Vec<PlayerRecord>,
+    pub openings: Vec<Vec<usize>>,
+    /// Milliseconds engine programs get per move
+    pub movetime: u64,
+    pub games: Vec<TournamentGame>,
+    /// Players who sat out a Swiss round for a point, as `(round, player)`
+    pub byes: Vec<(usize, usize)>,
+    /// Whether the external players' commands may be run
+    #[serde(skip)]
+    programs_allowed: bool,
+}
+
+/// A player's line in the standings
+#[derive(Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub player: usize,
+    pub points: f64,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    /// Sonneborn-Berger: the points of the opponents each game was won
+    /// against, plus half of those drawn against
+    pub tiebreak: f64,
+}
+
+impl TournamentGame {
+    /// Points for `o` and `x`, or `None` if the game has not been played
+    pub fn points(&self) -> Option<(f64, f64)> {
+        self.result.map(|result| match arena::winner(result) {
+            Some(Piece::O) => (1.0, 0.0),
+            Some(_) => (0.0, 1.0),
+            None => (0.5, 0.5),
+        })
+    }
+}
+
+impl Tournament {
+    /// A new tournament between `players`, who are trusted to be run since
+    /// they come from the caller.
+    ///
+    /// Swiss tournaments can have as many rounds as it takes everyone to
+    /// meet once, one fewer than the number of players rounded up to even.
+    pub fn new(format: Format, players: Vec<PlayerRecord>, openings: Vec<Vec<usize>>, movetime: Duration) -> Result<Tournament, &'static str> {
+        let mut tournament = Tournament {
+            format,
+            players,
+            openings,
+            movetime: movetime.as_millis() as u64,
+            games: Vec::new(),
+            byes: Vec::new(),
+            programs_allowed: true,
+        };
+        tournament.check()?;
+        if let Format::RoundRobin { cycles } = format {
+            tournament.schedule_round_robin(cycles);
+        }
+
+        Ok(tournament)
+    }
+
+    /// Reads a saved tournament. Its engine programs are not run until
+    /// [`Tournament::allow_programs`] is called.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Tournament> {
+        let json = fs::read_to_string(path)?;
+        let tournament: Tournament = serde_json::from_str(&json)?;
+        tournament.check().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        Ok(tournament)
+    }
+
+    /// Checks the players and rounds, and that every game and bye is
+    /// between entered players in a round the tournament has
+    fn check(&self) -> Result<(), &'static str> {
+        let players = &self.players;
+        if players.len() < 2 {
+            return Err("A tournament needs at least two players");
+        }
+        if players.iter().enumerate().any(|(i, player)| players[..i].contains(player)) {
+            return Err("Every player can only be entered once");
+        }
+
+        let rounds_to_meet = players.len().next_multiple_of(2) - 1;
+        let rounds = match self.format {
+            Format::RoundRobin { cycles } => cycles.saturating_mul(rounds_to_meet),
+            Format::Swiss { rounds } if rounds > rounds_to_meet => {
+                return Err("A Swiss tournament cannot have more rounds than it takes everyone to meet once");
+            }
+            Format::Swiss { rounds } => rounds,
+        };
+
+        let entered = |player: usize| player < players.len();
+        let games = self.games.iter().map(|game| (game.round, entered(game.o) && entered(game.x) && game.o != game.x));
+        let byes = self.byes.iter().map(|&(round, player)| (round, entered(player)));
+        for (round, players_entered) in games.chain(byes) {
+            if !players_entered {
+                return Err("A game or bye is for a player who was not entered");
+            }
+            if !(1..=rounds).contains(&round) {
+                return Err("A game or bye is in a round the tournament does not have");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// The commands of the external players, for asking whether to run
+    /// them
+    pub fn programs(&self) -> Vec<&str> {
+        self.players
+            .iter()
+            .filter_map(|player| match player {
+                PlayerRecord::External { command } => Some(command.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Lets the external players of a loaded tournament be run
+    pub fn allow_programs(&mut self) {
+        self.programs_allowed = true;
+    }
+
+    fn opening(&self, pairing: usize) -> Vec<usize> {
+        match self.openings.len() {
+            0 => Vec::new(),
+            n => self.openings[pairing % n].clone(),
+        }
+    }
+
+    /// Adds both games of a pairing
+    fn pair(&mut self, round: usize, a: usize, b: usize, pairing: usize) {
+        let opening = self.opening(pairing);
+        for (o, x) in [(a, b), (b, a)] {
+            self.games.push(TournamentGame {
+                round,
+                o,
+                x,
+                opening: opening.clone(),
+                result: None,
+                moves: Vec::new(),
+            });
+        }
+    }
+
+    /// Pairs everyone with the circle method: one player stays put while
+    /// the others rotate, with an empty spot when the count is odd
+    fn schedule_round_robin(&mut self, cycles: usize) {
+        let n = self.players.len();
+        let mut circle: Vec<Option<usize>> = (0..n).map(Some).collect();
+        if n % 2 == 1 {
+            circle.push(None);
+        }
+        let size = circle.len();
+
+        let mut pairing = 0;
+        for cycle in 0..cycles {
+            for turn in 0..size - 1 {
+                let round = cycle * (size - 1) + turn + 1;
+                for i in 0..size / 2 {
+                    if let (Some(a), Some(b)) = (circle[i], circle[size - 1 - i]) {
+                        self.pair(round, a, b, pairing);
+                        pairing += 1;
+                    }
+                }
+                circle[1..].rotate_right(1);
+            }
+        }
+    }
+
+    fn played(&self, a: usize, b: usize) -> bool {
+        self.games.iter().any(|game| (game.o, game.x) == (a, b) || (game.o, game.x) == (b, a))
+    }
+
+    /// Pairs the next Swiss round from the current standings, top down,
+    /// with each player meeting the highest one they have not met whose
+    /// pairing still lets everyone below be paired without rematches. With
+    /// an odd count the lowest player who has not had a bye sits out.
+    ///
+    /// The cap on rounds in [`Tournament::new`] leaves everyone someone new
+    /// to play, but a pairing of the rest can still be impossible, like when
+    /// the only two players left have met. Then the round is paired top down
+    /// with the highest opponent not yet met, and the rematch is played.
+    fn schedule_swiss_round(&mut self) {
+        let round = self.rounds_scheduled() + 1;
+        let mut order: Vec<usize> = self.standings().iter().map(|standing| standing.player).collect();
+
+        if order.len() % 2 == 1 {
+            let had_bye = |player: &usize| self.byes.iter().any(|&(_, p)| p == *player);
+            let bye = order.iter().rposition(|player| !had_bye(player)).unwrap_or(order.len() - 1);
+            self.byes.push((round, order.remove(bye)));
+        }
+
+        let pairs = self.pair_without_rematches(&order).unwrap_or_else(|| {
+            let mut pairs = Vec::new();
+            while let Some(a) = order.first().copied() {
+                order.remove(0);
+                let b = order.iter().position(|&b| !self.played(a, b)).unwrap_or(0);
+                pairs.push((a, order.remove(b)));
+            }
+            pairs
+        });
+
+        for (pairing, (a, b)) in (self.games.len() / 2..).zip(pairs) {
+            self.pair(round, a, b, pairing);
+        }
+    }
+
+    /// Pairs `order` top down with no rematches, trying the next opponent
+    /// down whenever the players left cannot be paired
+    fn pair_without_rematches(&self, order: &[usize]) -> Option<Vec<(usize, usize)>> {
+        let Some((&a, rest)) = order.split_first() else {
+            return Some(Vec::new());
+        };
+
+        rest.iter().enumerate().filter(|&(_, &b)| !self.played(a, b)).find_map(|(i, &b)| {
+            let mut others = rest.to_vec();
+            others.remove(i);
+            let mut pairs = self.pair_without_rematches(&others)?;
+            pairs.insert(0, (a, b));
+            Some(pairs)
+        })
+    }
+
+    fn rounds_scheduled(&self) -> usize {
+        let games = self.games.iter().map(|game| game.round);
+        let byes = self.byes.iter().map(|&(round, _)| round);
+        games.chain(byes).max().unwrap_or(0)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let all_played = self.games.iter().all(|game| game.result.is_some());
+        match self.format {
+            Format::RoundRobin { .. } => all_played,
+            Format::Swiss { rounds } => all_played && self.rounds_scheduled() >= rounds,
+        }
+    }
+
+    /// Plays every game that has not been played yet, pairing Swiss rounds
+    /// as they come. When `path` is given the tournament is saved there
+    /// after every game.
+    ///
+    /// Fails before any game if the tournament was loaded with external
+    /// players and [`Tournament::allow_programs`] was not called.
+    pub fn run(&mut self, path: Option<&Path>, mut progress: impl FnMut(&Tournament, &TournamentGame)) -> io::Result<()> {
+        let movetime = Duration::from_millis(self.movetime);
+        if !self.programs_allowed && !self.programs().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "The tournament file names engine programs that were not allowed to run",
+            ));
+        }
+
+        loop {
+            if let Some(i) = self.games.iter().position(|game| game.result.is_none()) {
+                let game = &self.games[i];
+                let record = arena::play_game(&self.players[game.o], &self.players[game.x], &game.opening, movetime)?;
+
+                let game = &mut self.games[i];
+                game.result = Some(record.result);
+                game.moves = record.moves;
+            } else if self.is_finished() {
+                return Ok(());
+            } else {
+                self.schedule_swiss_round();
+                continue;
+            }
+
+            if let Some(path) = path {
+                self.save(path)?;
+            }
+            let game = self.games.iter().rfind(|game| game.result.is_some()).expect("a game was just played");
+            progress(self, game);
+        }
+    }
+
+    /// Everyone sorted by points, then by tiebreak
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = (0..self.players.len())
+            .map(|player| Standing {
+                player,
+                points: 0.0,
+                wins: 0,
+                draws: 0,
+                losses: 0,
+                tiebreak: 0.0,
+            })
+            .collect();
+
+        for &(_, player) in &self.byes {
+            standings[player].points += 1.0;
+        }
+        for game in &self.games {
+            let Some((o, x)) = game.points() else { continue };
+            for (player, points) in [(game.o, o), (game.x, x)] {
+                let standing = &mut standings[player];
+                standing.points += points;
+                match points {
+                    1.0 => standing.wins += 1,
+                    0.0 => standing.losses += 1,
+                    _ => standing.draws += 1,
+                }
+            }
+        }
+
+        let points: Vec<f64> = standings.iter().map(|standing| standing.points).collect();
+        for game in &self.games {
+            let Some((o, x)) = game.points() else { continue };
+            standings[game.o].tiebreak += o * points[game.x];
+            standings[game.x].tiebreak += x * points[game.o];
+        }
+
+        standings.sort_by(|a, b| {
+            b.points
+                .total_cmp(&a.points)
+                .then(b.tiebreak.total_cmp(&a.tiebreak))
+                .then(a.player.cmp(&b.player))
+        });
+
+        standings
+    }
+
+    /// Points each player, by row, scored against each other player, by
+    /// column, in standings order
+    pub fn crosstable(&self) -> String {
+        let standings = self.standings();
+        let names: Vec<String> = self.players.iter().map(|player| player.to_string()).collect();
+        let width = names.iter().map(String::len).max().unwrap_or(0);
+
+        let mut table = format!("{:>3}  {:<width$}", "", "");
+        for rank in 1..=standings.len() {
+            let _ = write!(table, " {rank:>4}");
+        }
+        table.push_str("  Points\n");
+
+        for (rank, row) in standings.iter().enumerate() {
+            let _ = write!(table, "{:>3}. {:<width$}", rank + 1, names[row.player]);
+            for column in &standings {
+                if column.player == row.player {
+                    table.push_str("    -");
+                    continue;
+                }
+
+                let mut points = None;
+                for game in &self.games {
+                    let Some((o, x)) = game.points() else { continue };
+                    if (game.o, game.x) == (row.player, column.player) {
+                        *points.get_or_insert(0.0) += o;
+                    } else if (game.o, game.x) == (column.player, row.player) {
+                        *points.get_or_insert(0.0) += x;
+                    }
+                }
+                let cell = points.map(|points: f64| points.to_string()).unwrap_or(".".to_string());
+                let _ = write!(table, " {cell:>4}");
+            }
+            let _ = writeln!(table, "  {}", row.points);
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod tournament_tests {
+    use super::*;
+
+    fn engines(depths: &[u32]) -> Vec<PlayerRecord> {
+        depths.iter().map(|&depth| PlayerRecord::Engine { depth }).collect()
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_once() {
+        let tournament = Tournament::new(Format::RoundRobin { cycles: 1 }, engines(&[1, 2, 3, 4, 5]), Vec::new(), Duration::ZERO).unwrap();
+
+        // Ten pairings of two games each
+        assert_eq!(tournament.games.len(), 20);
+        for a in 0..5 {
+            for b in 0..5 {
+                let meetings = tournament.games.iter().filter(|game| (game.o, game.x) == (a, b)).count();
+                assert_eq!(meetings, usize::from(a != b));
+            }
+        }
+    }
+
+    #[test]
+    fn swiss_resumes_from_a_saved_file() {
+        let path = std::env::temp_dir().join(format!("swiss-{}.json", std::process::id()));
+        let mut stopped = Tournament::new(Format::Swiss { rounds: 2 }, engines(&[1, 2, 3]), vec![vec![3]], Duration::ZERO).unwrap();
+
+        // Stop after the first game, as if the process was killed
+        stopped.schedule_swiss_round();
+        let first = &stopped.games[0];
+        let record = arena::play_game(&stopped.players[first.o], &stopped.players[first.x], &[3], Duration::ZERO).unwrap();
+        stopped.games[0].result = Some(record.result);
+        stopped.save(&path).unwrap();
+
+        let mut resumed = Tournament::load(&path).unwrap();
+        resumed.run(Some(&path), |_, _| {}).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert!(resumed.is_finished());
+        assert_eq!(resumed.byes.len(), 2);
+        assert_ne!(resumed.byes[0].1, resumed.byes[1].1);
+        assert_eq!(resumed.games.len(), 4);
+        let total: f64 = resumed.standings().iter().map(|standing| standing.points).sum();
+        assert_eq!(total, 4.0 + 2.0);
+        assert!(resumed.crosstable().contains("engine:3"));
+    }
+
+    #[test]
+    fn swiss_pairs_around_rematches() {
+        assert!(Tournament::new(Format::Swiss { rounds: 4 }, engines(&[1, 2, 3, 4]), Vec::new(), Duration::ZERO).is_err());
+        assert!(Tournament::new(Format::Swiss { rounds: 5 }, engines(&[1, 2, 3, 4, 5]), Vec::new(), Duration::ZERO).is_ok());
+
+        let mut tournament = Tournament::new(Format::Swiss { rounds: 3 }, engines(&[1, 2, 3, 4]), Vec::new(), Duration::ZERO).unwrap();
+        tournament.pair(1, 2, 3, 0);
+
+        // Top down, 0 would meet 1 and leave 2 and 3 a rematch
+        assert_eq!(tournament.pair_without_rematches(&[0, 1, 2, 3]), Some(vec![(0, 2), (1, 3)]));
+
+        // Every round has someone new for everyone
+        let mut tournament = Tournament::new(Format::Swiss { rounds: 3 }, engines(&[1, 2, 3, 4]), Vec::new(), Duration::ZERO).unwrap();
+        tournament.run(None, |_, _| {}).unwrap();
+        for a in 0..4 {
+            for b in a + 1..4 {
+                let meetings = tournament.games.iter().filter(|game| (game.o, game.x) == (a, b) || (game.o, game.x) == (b, a));
+                assert_eq!(meetings.count(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn engines_with_other_evals_and_mcts_can_enter() {
+        let players = vec![
+            PlayerRecord::Engine { depth: 2 },
+            PlayerRecord::Tuned { depth: 2, weights: [0, 1, 50, 2000] },
+            PlayerRecord::Mcts { playouts: 50 },
+        ];
+        let mut tournament = Tournament::new(Format::RoundRobin { cycles: 1 }, players, vec![vec![3]], Duration::ZERO).unwrap();
+
+        tournament.run(None, |_, _| {}).unwrap();
+
+        assert!(tournament.is_finished());
+        let crosstable = tournament.crosstable();
+        assert!(crosstable.contains("tuned:2:0,1,50,2000"));
+        assert!(crosstable.contains("mcts:50"));
+    }
+
+    #[test]
+    fn loaded_files_only_name_entered_players_and_rounds() {
+        let path = std::env::temp_dir().join(format!("broken-{}.json", std::process::id()));
+        let tournament = Tournament::new(Format::Swiss { rounds: 2 }, engines(&[1, 2, 3]), Vec::new(), Duration::ZERO).unwrap();
+        let broken = |change: fn(&mut Tournament)| {
+            let mut broken = tournament.clone();
+            change(&mut broken);
+            broken.save(&path).unwrap();
+            Tournament::load(&path).unwrap_err()
+        };
+
+        let error = broken(|t| t.pair(1, 0, 3, 0));
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("not entered"));
+        assert!(broken(|t| t.byes.push((1, 7))).to_string().contains("not entered"));
+        assert!(broken(|t| t.pair(3, 0, 1, 0)).to_string().contains("round"));
+        assert!(broken(|t| t.format = Format::Swiss { rounds: 9 }).to_string().contains("rounds"));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn loaded_programs_must_be_allowed() {
+        let path = std::env::temp_dir().join(format!("programs-{}.json", std::process::id()));
+        let mut players = engines(&[1]);
+        players.push(PlayerRecord::External { command: "touch never-run".to_string() });
+        Tournament::new(Format::RoundRobin { cycles: 1 }, players, Vec::new(), Duration::ZERO)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+
+        let mut loaded = Tournament::load(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.programs(), ["touch never-run"]);
+        let error = loaded.run(None, |_, _| {}).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(loaded.games.iter().all(|game| game.result.is_none()));
+    }
+}
@@ -0,0 +1,356 @@
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::style::Print;
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::board::{self, Piece};
+use crate::engine::Engine;
+use crate::render::Marks;
+use crate::theme::{Mark, Theme};
+use crate::{Action, Game, Player, now};
+
+const ROWS: usize = 6;
+const COLS: usize = 7;
+const BOARD_LEFT: u16 = 2;
+const BOARD_TOP: u16 = 3;
+const PANEL_LEFT: u16 = 24;
+const DROP_FRAME: Duration = Duration::from_millis(35);
+/// How often a running clock is redrawn, and chat from a network game is
+/// checked for, while waiting for a key
+const CLOCK_TICK: Duration = Duration::from_millis(100);
+
+const KEYS: &str = "\
+←/→ move   Enter drop   1-7 drop in column
+h hint   u undo   r redo   f flip sides
+d offer draw   x resign   q quit";
+
+/// Restores the terminal when dropped, even if the game panics
+struct RawMode;
+
+impl RawMode {
+    fn enable(out: &mut Stdout) -> io::Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+/// A piece that is on its way down a column during the drop animation
+struct Falling {
+    col: usize,
+    row: usize,
+    piece: Piece,
+}
+
+/// Full screen terminal front end for [`Game`], where humans pick a column
+/// with the arrow keys and the board, eval and move list stay on screen
+pub struct Tui<'a> {
+    game: &'a mut Game,
+    out: Stdout,
+    cursor: usize,
+    status: String,
+    theme: Theme,
+}
+
+impl<'a> Tui<'a> {
+    pub fn new(game: &'a mut Game) -> Tui<'a> {
+        Tui {
+            game,
+            out: io::stdout(),
+            cursor: 3,
+            status: String::new(),
+            theme: Theme::detect(),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let _raw_mode = RawMode::enable(&mut self.out)?;
+
+        loop {
+            self.draw(None)?;
+
+            if let Some(outcome) = self.game.outcome() {
+                self.game.finished.get_or_insert_with(now);
+                self.status = format!("{outcome} Press any key to leave");
+                self.draw(None)?;
+                wait_for_key()?;
+                return Ok(());
+            }
+
+            let piece = self.game.board.to_move();
+            self.game.start_clock(piece);
+            if !self.game.is_human(piece) {
+                self.status = match self.game.player(piece) {
+                    Player::Remote(remote) => format!("Waiting for {}...", remote.peer),
+                    _ => "Thinking...".to_string(),
+                };
+                self.draw(None)?;
+                self.status.clear();
+            }
+
+            if let Some((col, eval)) = self.game.pondered(piece) {
+                self.game.board.eval = eval;
+                self.animate_drop(col, piece)?;
+                self.game.apply(Action::Move(col));
+                continue;
+            }
+            self.game.start_pondering(piece);
+
+            let action = match self.game.player(piece) {
+                Player::Human(_) => match self.read_action(piece)? {
+                    Some(action) => action,
+                    None => {
+                        self.game.check_flag(piece);
+                        continue;
+                    }
+                },
+                Player::Engine(engine) => {
+                    let (col, eval) = engine.best_move(&self.game.board);
+                    self.game.board.eval = eval;
+                    Action::Move(col)
+                }
+                Player::Mcts(_) | Player::Remote(_) | Player::External(_) => self.game.ask(piece),
+            };
+            if self.game.check_flag(piece) {
+                continue;
+            }
+
+            // A draw offered to the human here is answered with a key, as
+            // the line based prompt does not work in raw mode
+            if let Action::OfferDraw = action
+                && self.game.is_human(piece.opponent())
+            {
+                self.status = format!("{piece:?} offers a draw, accept? (y/n)");
+                self.draw(None)?;
+                let accepted = read_yes_no()?;
+                self.status = self.game.settle_draw(piece, accepted);
+                continue;
+            }
+
+            if let Action::Move(col) = action {
+                self.animate_drop(col, piece)?;
+            }
+
+            match self.game.apply(action) {
+                Some(message) => {
+                    if !message.is_empty() {
+                        self.status = message;
+                    }
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Waits for keys until the human picks a column or a command, or
+    /// returns `None` when their clock runs out first
+    fn read_action(&mut self, piece: Piece) -> io::Result<Option<Action>> {
+        loop {
+            if (self.game.clock.is_some() || self.game.is_networked()) && !event::poll(CLOCK_TICK)? {
+                if self.game.clock.as_ref().is_some_and(|clock| clock.is_flagged(piece)) {
+                    return Ok(None);
+                }
+                if let Some(chat) = self.game.pending_chat() {
+                    self.status = chat.replace('\n', "  ");
+                }
+                self.draw(None)?;
+                continue;
+            }
+
+            let Event::Key(KeyEvent { code, kind: KeyEventKind::Press, .. }) = event::read()? else {
+                continue;
+            };
+
+            match code {
+                KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+                KeyCode::Right => self.cursor = (self.cursor + 1).min(COLS - 1),
+                KeyCode::Enter | KeyCode::Down | KeyCode::Char(' ') => {
+                    if let Some(action) = self.drop_at(self.cursor) {
+                        return Ok(Some(action));
+                    }
+                }
+                KeyCode::Char(c @ '1'..='7') => {
+                    self.cursor = c as usize - '1' as usize;
+                    if let Some(action) = self.drop_at(self.cursor) {
+                        return Ok(Some(action));
+                    }
+                }
+                KeyCode::Char('h') => {
+                    self.status = match Engine::default().hint(&self.game.board) {
+                        Some(hint) => {
+                            self.cursor = hint.col;
+                            hint.to_string()
+                        }
+                        None => "The game is over".to_string(),
+                    };
+                }
+                KeyCode::Char('u') => return Ok(Some(Action::Undo)),
+                KeyCode::Char('r') => return Ok(Some(Action::Redo)),
+                KeyCode::Char('f') => return Ok(Some(Action::Flip)),
+                KeyCode::Char('d') => return Ok(Some(Action::OfferDraw)),
+                KeyCode::Char('x') => return Ok(Some(Action::Resign)),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(Some(Action::Quit)),
+                _ => continue,
+            }
+
+            self.draw(None)?;
+        }
+    }
+
+    fn drop_at(&mut self, col: usize) -> Option<Action> {
+        if self.game.board.get_moves().contains(&col) {
+            self.status.clear();
+            Some(Action::Move(col))
+        } else {
+            self.status = "Column is full".to_string();
+            None
+        }
+    }
+
+    fn animate_drop(&mut self, col: usize, piece: Piece) -> io::Result<()> {
+        let landing = self.game.board.column_height(col);
+        if landing >= ROWS {
+            return Ok(());
+        }
+
+        for row in (landing..ROWS).rev() {
+            self.draw(Some(Falling { col, row, piece }))?;
+            thread::sleep(DROP_FRAME);
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, falling: Option<Falling>) -> io::Result<()> {
+        let board = &self.game.board;
+        let to_move = board.to_move();
+        let out = &mut self.out;
+
+        queue!(
+            out,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(BOARD_LEFT, 0),
+            Print("Four in a row")
+        )?;
+
+        // The cursor row shows the piece that is about to be dropped
+        if self.game.outcome().is_none() && falling.is_none() {
+            queue!(
+                out,
+                cursor::MoveTo(BOARD_LEFT + 1 + self.cursor as u16 * 2, BOARD_TOP - 1),
+                Print(self.theme.paint(to_move))
+            )?;
+        }
+
+        // Marks would point at the wrong piece while another one is falling
+        let marks = Marks::of(board);
+        let show_marks = falling.is_none();
+
+        for row in 0..ROWS {
+            let y = BOARD_TOP + (ROWS - 1 - row) as u16;
+            queue!(out, cursor::MoveTo(BOARD_LEFT, y), Print("|"))?;
+            for col in 0..COLS {
+                let piece = match &falling {
+                    Some(f) if f.col == col && f.row == row => f.piece,
+                    _ => board.get(col, row),
+                };
+                let mark = if show_marks { marks.at(col, row) } else { Mark::None };
+                queue!(
+                    out,
+                    Print(self.theme.paint_marked(piece, mark)),
+                    Print(if col == COLS - 1 { "|" } else { " " })
+                )?;
+            }
+        }
+
+        let bottom = BOARD_TOP + ROWS as u16;
+        queue!(
+            out,
+            cursor::MoveTo(BOARD_LEFT, bottom),
+            Print("+-------------+"),
+            cursor::MoveTo(BOARD_LEFT + 1, bottom + 1),
+            Print("1 2 3 4 5 6 7")
+        )?;
+
+        self.draw_panel()?;
+
+        let keys_top = BOARD_TOP + ROWS as u16 + 3;
+        for (i, line) in KEYS.lines().enumerate() {
+            queue!(self.out, cursor::MoveTo(BOARD_LEFT, keys_top + i as u16), Print(line))?;
+        }
+
+        self.out.flush()
+    }
+
+    /// Side panel with whose turn it is, the eval, the move list and the
+    /// latest status message
+    fn draw_panel(&mut self) -> io::Result<()> {
+        let game = &self.game;
+        let to_move = game.board.to_move();
+        let name = |piece| game.player(piece).record().to_string();
+
+        let mut lines = vec![
+            format!("O: {}", name(Piece::O)),
+            format!("X: {}", name(Piece::X)),
+            String::new(),
+            format!("To move: {to_move:?}"),
+            format!("Eval: {}", game.board.eval),
+            String::new(),
+            "Moves:".to_string(),
+        ];
+        if let Some(clock) = &game.clock {
+            lines.insert(5, format!("Clock: {clock}"));
+        }
+
+        let numbered: Vec<String> = game
+            .moves
+            .chunks(2)
+            .enumerate()
+            .map(|(i, pair)| format!("{}. {}", i + 1, board::format_moves(pair)))
+            .collect();
+        // Only the most recent moves fit next to the board
+        let shown = numbered.len().saturating_sub(ROWS);
+        lines.extend(numbered.into_iter().skip(shown));
+
+        for (i, line) in lines.iter().enumerate() {
+            queue!(self.out, cursor::MoveTo(PANEL_LEFT, 1 + i as u16), Print(line))?;
+        }
+
+        let status_y = BOARD_TOP + ROWS as u16 + 7;
+        queue!(self.out, cursor::MoveTo(BOARD_LEFT, status_y), Print(&self.status))
+    }
+}
+
+fn wait_for_key() -> io::Result<()> {
+    loop {
+        if let Event::Key(KeyEvent { kind: KeyEventKind::Press, .. }) = event::read()? {
+            return Ok(());
+        }
+    }
+}
+
+fn read_yes_no() -> io::Result<bool> {
+    loop {
+        if let Event::Key(KeyEvent { code, kind: KeyEventKind::Press, .. }) = event::read()? {
+            match code {
+                KeyCode::Char('y') => return Ok(true),
+                KeyCode::Char('n') | KeyCode::Esc => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+}